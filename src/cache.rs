@@ -0,0 +1,750 @@
+use crate::mesh::{Elements, MeshData, FLOATS_PER_VERTEX};
+use crate::scene::{
+    BoundingBox, Color, FaceVertex, Group, Material, Normal, Object, Scene, TextureCoordinate,
+    Triangle, Vertex, VertexProperty,
+};
+use crate::tuple::vector;
+use memmap2::Mmap;
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+// A parsed scene is saved next to its OBJ as `<file>.cache`, with its
+// simplified levels of detail and their meshes as the GPU takes them, so that
+// the next launch maps it instead of parsing, simplifying and indexing again.
+//
+// Everything is little endian: the magic and version, then the files the
+// scene was built from (the OBJ and its material libraries) with their size
+// and modification time, a hash of their contents, the bounding box, the
+// scene arrays, each prefixed by its length, the simplified levels laid out
+// the same way, and the meshes. Their vertex and index buffers are aligned to
+// 4 bytes, to be read in place.
+
+// Bumped whenever the layout changes, older caches are then rebuilt
+const VERSION: u32 = 4;
+const MAGIC: &[u8; 8] = b"SCOPSCN\0";
+// Stands for a missing optional index
+const NONE: u32 = u32::MAX;
+
+fn cache_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".cache");
+    PathBuf::from(name)
+}
+
+// Size and modification time of a source file, checked before hashing it. A
+// missing file stamps as zeros, so that creating it invalidates the cache.
+#[derive(PartialEq, Default)]
+struct Stamp {
+    size: u64,
+    seconds: u64,
+    nanoseconds: u32,
+}
+
+fn stamp(path: &Path) -> Stamp {
+    let Ok(metadata) = fs::metadata(path) else {
+        return Stamp::default();
+    };
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .unwrap_or_default();
+    Stamp {
+        size: metadata.len(),
+        seconds: modified.as_secs(),
+        nanoseconds: modified.subsec_nanos(),
+    }
+}
+
+// FNV-1a over 64-bit words rather than bytes, which is enough to tell an
+// edited file from the cached one and fast enough not to eat what the cache
+// saves
+fn hash(hash: u64, bytes: &[u8]) -> u64 {
+    let words = bytes.chunks(8).map(|chunk| {
+        let mut word = [0; 8];
+        word[..chunk.len()].copy_from_slice(chunk);
+        u64::from_le_bytes(word)
+    });
+    words.fold(hash, |hash, word| (hash ^ word).wrapping_mul(0x100000001b3))
+}
+
+const HASH_SEED: u64 = 0xcbf29ce484222325;
+
+fn map(path: &Path) -> io::Result<Option<Mmap>> {
+    let file = File::open(path)?;
+    // Empty files cannot be mapped
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    // The mapping is only read while the file is not expected to change
+    unsafe { Mmap::map(&file) }.map(Some)
+}
+
+// The OBJ followed by the material libraries it names, resolved the way the
+// parser does
+fn sources(path: &Path) -> io::Result<(Vec<PathBuf>, u64)> {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let mut sources = vec![path.to_path_buf()];
+    let mut digest = HASH_SEED;
+    if let Some(obj) = map(path)? {
+        digest = hash(digest, &obj);
+        let libraries = obj
+            .split(|&byte| byte == b'\n')
+            .filter(|line| line.trim_ascii_start().starts_with(b"mtllib"));
+        for line in libraries {
+            let line = String::from_utf8_lossy(line);
+            let mut tokens = line.split_whitespace();
+            if tokens.next() == Some("mtllib") {
+                sources.extend(tokens.map(|name| directory.join(name)));
+            }
+        }
+    }
+    // A missing library is hashed as empty, the parser skips it as well
+    for library in sources[1..].iter() {
+        if let Ok(Some(bytes)) = map(library) {
+            digest = hash(digest, &bytes);
+        }
+    }
+    Ok((sources, digest))
+}
+
+/// A cache mapped in memory. The scene and its simplified levels of detail
+/// are decoded from it, while the meshes of every level, made with the
+/// default view options, stay in the mapping until they are uploaded.
+pub struct Cache {
+    /// The levels of detail after the scene itself, one per mesh but the
+    /// first.
+    pub lods: Vec<Scene>,
+    map: Mmap,
+    meshes: Vec<MeshLayout>,
+}
+
+// Where the buffers of a mesh lie in the mapping, as byte ranges
+struct MeshLayout {
+    vertices: Range<usize>,
+    indices: Range<usize>,
+    wide: bool,
+    triangle_indices: usize,
+    line_indices: usize,
+    point_indices: usize,
+    materials: Vec<(Option<usize>, Range<usize>)>,
+}
+
+impl Cache {
+    /// The meshes of the levels of detail, borrowing their buffers from the
+    /// mapping.
+    pub fn meshes(&self) -> Vec<MeshData<'_>> {
+        self.meshes
+            .iter()
+            .map(|layout| layout.mesh(&self.map))
+            .collect()
+    }
+}
+
+impl MeshLayout {
+    // The ranges were checked to cast when the cache was loaded
+    fn mesh<'a>(&self, bytes: &'a [u8]) -> MeshData<'a> {
+        let indices = &bytes[self.indices.clone()];
+        MeshData {
+            vertices: Cow::Borrowed(cast(&bytes[self.vertices.clone()]).unwrap()),
+            indices: match self.wide {
+                false => Elements::U16(Cow::Borrowed(cast(indices).unwrap())),
+                true => Elements::U32(Cow::Borrowed(cast(indices).unwrap())),
+            },
+            triangle_indices: self.triangle_indices,
+            line_indices: self.line_indices,
+            point_indices: self.point_indices,
+            materials: self.materials.clone(),
+        }
+    }
+}
+
+// Numbers read in place. Any bits make a valid f32, u16 or u32, which are the
+// only types this is used for, so only the alignment can fail.
+fn cast<T: Copy>(bytes: &[u8]) -> Option<&[T]> {
+    let (prefix, values, suffix) = unsafe { bytes.align_to::<T>() };
+    (prefix.is_empty() && suffix.is_empty()).then_some(values)
+}
+
+/// Loads the cache of the OBJ at `path`, None when there is none or when it
+/// is stale or unreadable, in which case the OBJ has to be parsed.
+pub fn load(path: &Path) -> Option<(Scene, Cache)> {
+    // Buffers read in place have to be in the machine's byte order
+    if cfg!(target_endian = "big") {
+        return None;
+    }
+    let cache = map(&cache_path(path)).ok()??;
+    let mut reader = Reader {
+        bytes: &cache,
+        offset: 0,
+    };
+    if reader.take(MAGIC.len())? != MAGIC || reader.u32()? != VERSION {
+        return None;
+    }
+    let mut files = Vec::new();
+    for _ in 0..reader.count(28)? {
+        let file = PathBuf::from(reader.string()?);
+        let cached = Stamp {
+            size: reader.u64()?,
+            seconds: reader.u64()?,
+            nanoseconds: reader.u32()?,
+        };
+        if stamp(&file) != cached {
+            return None;
+        }
+        files.push(file);
+    }
+    let digest = reader.u64()?;
+    // Size and time can match an edited file, the contents cannot
+    let (sources, current) = sources(path).ok()?;
+    if sources != files || digest != current {
+        return None;
+    }
+    let bounds = reader.bounds()?;
+    let scene = reader.scene()?;
+    // Bounds recomputed from the vertices read back catch a corrupted file
+    let same = match (bounds, scene.bounding_box()) {
+        (None, None) => true,
+        (Some(a), Some(b)) => a.min == b.min && a.max == b.max,
+        _ => false,
+    };
+    if !same {
+        return None;
+    }
+    let mut lods = Vec::new();
+    for _ in 0..reader.count(1)? {
+        lods.push(reader.scene()?);
+    }
+    if reader.u32()? as usize != FLOATS_PER_VERTEX {
+        return None;
+    }
+    let mut meshes = Vec::new();
+    for _ in 0..reader.count(1)? {
+        meshes.push(reader.mesh()?);
+    }
+    let valid = meshes
+        .iter()
+        .all(|layout| check_mesh(&layout.mesh(&cache), scene.materials.len()));
+    if meshes.len() != lods.len() + 1 || !valid || reader.offset != cache.len() {
+        return None;
+    }
+    let cache = Cache {
+        lods,
+        map: cache,
+        meshes,
+    };
+    Some((scene, cache))
+}
+
+// Elements past the vertices would have the GPU read out of bounds
+fn check_mesh(mesh: &MeshData, materials: usize) -> bool {
+    let vertices = mesh.vertices.len() / FLOATS_PER_VERTEX;
+    let (count, in_range) = match &mesh.indices {
+        Elements::U16(indices) => (
+            indices.len(),
+            indices.iter().all(|&i| (i as usize) < vertices),
+        ),
+        Elements::U32(indices) => (
+            indices.len(),
+            indices.iter().all(|&i| (i as usize) < vertices),
+        ),
+    };
+    let runs = mesh.materials.iter().all(|(material, indices)| {
+        material.is_none_or(|material| material < materials)
+            && indices.start <= indices.end
+            && indices.end <= mesh.triangle_indices
+    });
+    mesh.vertices.len().is_multiple_of(FLOATS_PER_VERTEX)
+        && in_range
+        && runs
+        && mesh
+            .triangle_indices
+            .checked_add(mesh.line_indices)
+            .and_then(|sum| sum.checked_add(mesh.point_indices))
+            == Some(count)
+}
+
+/// Writes the cache of the OBJ at `path` from the levels of detail
+/// `model::prepare` made of it, the parsed scene first, and their meshes made
+/// with the default view options. The file is written aside and renamed, so
+/// an interrupted write leaves no bad cache.
+pub fn save(lods: &[Scene], meshes: &[MeshData], path: &Path) -> io::Result<()> {
+    let scene = &lods[0];
+    let (sources, digest) = sources(path)?;
+    let cache = cache_path(path);
+    let mut temporary = cache.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut out = Writer {
+        out: BufWriter::new(File::create(&temporary)?),
+        written: 0,
+    };
+    out.bytes(MAGIC)?;
+    out.u32(VERSION)?;
+    out.count(sources.len())?;
+    for source in sources.iter() {
+        let stamp = stamp(source);
+        out.string(&source.to_string_lossy())?;
+        out.u64(stamp.size)?;
+        out.u64(stamp.seconds)?;
+        out.u32(stamp.nanoseconds)?;
+    }
+    out.u64(digest)?;
+    out.bounds(scene.bounding_box())?;
+    out.scene(scene)?;
+    out.count(lods.len() - 1)?;
+    for lod in lods[1..].iter() {
+        out.scene(lod)?;
+    }
+    out.u32(FLOATS_PER_VERTEX as u32)?;
+    out.count(meshes.len())?;
+    for mesh in meshes {
+        out.mesh(mesh)?;
+    }
+    out.out.flush()?;
+    drop(out);
+    fs::rename(&temporary, &cache)
+}
+
+// `i`th little endian word of an array element
+fn word(bytes: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes(bytes[i * 4..i * 4 + 4].try_into().unwrap())
+}
+
+fn float(bytes: &[u8], i: usize) -> f32 {
+    f32::from_bits(word(bytes, i))
+}
+
+fn optional(index: u32) -> Option<usize> {
+    (index != NONE).then_some(index as usize)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+// Every read fails with None past the end, so a truncated cache is just a
+// stale one
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let end = self.offset.checked_add(len)?;
+        let bytes = self.bytes.get(self.offset..end)?;
+        self.offset = end;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        Some(f32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    // Element count, bounded by what the rest of the file could hold so that
+    // a corrupted one cannot make us allocate gigabytes
+    fn count(&mut self, element_size: usize) -> Option<usize> {
+        let count = self.u64()? as usize;
+        let left = self.bytes.len() - self.offset;
+        (count.checked_mul(element_size)? <= left).then_some(count)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        let len = self.count(1)?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    // Length-prefixed array of elements made of `words` 32-bit words each,
+    // taken in one piece since this is where the bulk of the file is
+    fn array<T>(&mut self, words: usize, element: impl Fn(&[u8]) -> T) -> Option<Vec<T>> {
+        let count = self.count(words * 4)?;
+        let bytes = self.take(count * words * 4)?;
+        Some(bytes.chunks_exact(words * 4).map(element).collect())
+    }
+
+    // Length-prefixed array of `size` byte numbers left where it is, after
+    // the padding that aligns it to 4 bytes. Returns its byte range.
+    fn in_place<T: Copy>(&mut self, size: usize) -> Option<Range<usize>> {
+        let count = self.count(size)?;
+        self.offset = self.offset.div_ceil(4) * 4;
+        let start = self.offset;
+        let bytes = self.take(count * size)?;
+        cast::<T>(bytes)?;
+        Some(start..self.offset)
+    }
+
+    fn mesh(&mut self) -> Option<MeshLayout> {
+        let triangle_indices = self.u64()? as usize;
+        let line_indices = self.u64()? as usize;
+        let point_indices = self.u64()? as usize;
+        let mut materials = Vec::new();
+        for _ in 0..self.count(20)? {
+            materials.push((optional(self.u32()?), self.range()?));
+        }
+        let vertices = self.in_place::<f32>(4)?;
+        let (wide, indices) = match self.u8()? {
+            2 => (false, self.in_place::<u16>(2)?),
+            4 => (true, self.in_place::<u32>(4)?),
+            _ => return None,
+        };
+        Some(MeshLayout {
+            vertices,
+            indices,
+            wide,
+            triangle_indices,
+            line_indices,
+            point_indices,
+            materials,
+        })
+    }
+
+    fn path(&mut self) -> Option<Option<PathBuf>> {
+        Some(match self.u8()? {
+            0 => None,
+            _ => Some(PathBuf::from(self.string()?)),
+        })
+    }
+
+    fn color(&mut self) -> Option<Color> {
+        Some(Color::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+
+    fn bounds(&mut self) -> Option<Option<BoundingBox>> {
+        if self.u8()? == 0 {
+            return Some(None);
+        }
+        let min = vector(self.f32()?, self.f32()?, self.f32()?);
+        let max = vector(self.f32()?, self.f32()?, self.f32()?);
+        Some(Some(BoundingBox::new(min, max)))
+    }
+
+    fn range(&mut self) -> Option<Range<usize>> {
+        Some(self.u64()? as usize..self.u64()? as usize)
+    }
+
+    fn scene(&mut self) -> Option<Scene> {
+        let mut scene = Scene::new();
+        scene.vertices = self.array(4, |v| {
+            Vertex::new(float(v, 0), float(v, 1), float(v, 2), float(v, 3))
+        })?;
+        scene.vertex_colors =
+            self.array(4, |c| [float(c, 0), float(c, 1), float(c, 2), float(c, 3)])?;
+        for _ in 0..self.count(16)? {
+            let name = self.string()?;
+            let values = self.array(1, |value| float(value, 0))?;
+            scene
+                .vertex_properties
+                .push(VertexProperty { name, values });
+        }
+        scene.texture_coordinates = self.array(3, |vt| {
+            TextureCoordinate::new(float(vt, 0), float(vt, 1), float(vt, 2))
+        })?;
+        scene.normals = self.array(3, |vn| {
+            Normal::new(float(vn, 0), float(vn, 1), float(vn, 2))
+        })?;
+        scene.triangles = self.array(11, |t| {
+            let corner = |i: usize| FaceVertex {
+                vertex: word(t, i * 3) as usize,
+                texture: optional(word(t, i * 3 + 1)),
+                normal: optional(word(t, i * 3 + 2)),
+            };
+            Triangle::new(
+                [corner(0), corner(1), corner(2)],
+                word(t, 9),
+                optional(word(t, 10)),
+            )
+        })?;
+        scene.lines = self.array(2, |l| [word(l, 0) as usize, word(l, 1) as usize])?;
+        scene.points = self.array(1, |p| word(p, 0) as usize)?;
+        for _ in 0..self.count(32)? {
+            let mut object = Object::new(&self.string()?, 0);
+            object.triangles = self.range()?;
+            for _ in 0..self.count(24)? {
+                let mut names = Vec::new();
+                for _ in 0..self.count(8)? {
+                    names.push(self.string()?);
+                }
+                let mut group = Group::new(names, 0);
+                group.triangles = self.range()?;
+                object.groups.push(group);
+            }
+            scene.objects.push(object);
+        }
+        for _ in 0..self.count(8)? {
+            let mut material = Material::new(&self.string()?);
+            material.ambient = self.color()?;
+            material.diffuse = self.color()?;
+            material.specular = self.color()?;
+            material.emissive = self.color()?;
+            material.shininess = self.f32()?;
+            material.optical_density = self.f32()?;
+            material.dissolve = self.f32()?;
+            material.illumination = self.u32()?;
+            material.diffuse_map = self.path()?;
+            material.specular_map = self.path()?;
+            material.bump_map = self.path()?;
+            material.dissolve_map = self.path()?;
+            scene.materials.push(material);
+        }
+        self.check(&scene).then_some(scene)
+    }
+
+    // Indices out of range would make the renderer panic later on
+    fn check(&self, scene: &Scene) -> bool {
+        let vertex = |i: usize| i < scene.vertices.len();
+        let texture = |i: Option<usize>| i.is_none_or(|i| i < scene.texture_coordinates.len());
+        let normal = |i: Option<usize>| i.is_none_or(|i| i < scene.normals.len());
+        let material = |i: Option<usize>| i.is_none_or(|i| i < scene.materials.len());
+        let range = |r: &Range<usize>| r.start <= r.end && r.end <= scene.triangles.len();
+        let per_vertex = |len: usize| len == scene.vertices.len();
+        let colors = scene.vertex_colors.is_empty() || per_vertex(scene.vertex_colors.len());
+        colors
+            && scene
+                .vertex_properties
+                .iter()
+                .all(|property| per_vertex(property.values.len()))
+            && scene.triangles.iter().all(|t| {
+                material(t.material)
+                    && t.corners
+                        .iter()
+                        .all(|c| vertex(c.vertex) && texture(c.texture) && normal(c.normal))
+            })
+            && scene.lines.iter().flatten().all(|&i| vertex(i))
+            && scene.points.iter().all(|&i| vertex(i))
+            && scene
+                .objects
+                .iter()
+                .all(|o| range(&o.triangles) && o.groups.iter().all(|g| range(&g.triangles)))
+    }
+}
+
+struct Writer<W: Write> {
+    out: W,
+    written: usize,
+}
+
+impl<W: Write> Writer<W> {
+    fn bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.written += bytes.len();
+        self.out.write_all(bytes)
+    }
+
+    // Pads to the next 4 byte boundary, for buffers read in place
+    fn align(&mut self) -> io::Result<()> {
+        let padding = self.written.div_ceil(4) * 4 - self.written;
+        self.bytes(&[0; 3][..padding])
+    }
+
+    fn u8(&mut self, value: u8) -> io::Result<()> {
+        self.bytes(&[value])
+    }
+
+    fn u32(&mut self, value: u32) -> io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn u64(&mut self, value: u64) -> io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn f32(&mut self, value: f32) -> io::Result<()> {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    fn count(&mut self, count: usize) -> io::Result<()> {
+        self.u64(count as u64)
+    }
+
+    fn string(&mut self, value: &str) -> io::Result<()> {
+        self.count(value.len())?;
+        self.bytes(value.as_bytes())
+    }
+
+    // Indices are stored on 32 bits, NONE excluded
+    fn index(&mut self, index: usize) -> io::Result<()> {
+        match u32::try_from(index) {
+            Ok(index) if index != NONE => self.u32(index),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "index too large for the cache",
+            )),
+        }
+    }
+
+    fn optional_index(&mut self, index: Option<usize>) -> io::Result<()> {
+        match index {
+            Some(index) => self.index(index),
+            None => self.u32(NONE),
+        }
+    }
+
+    fn path(&mut self, path: &Option<PathBuf>) -> io::Result<()> {
+        match path {
+            Some(path) => {
+                self.u8(1)?;
+                self.string(&path.to_string_lossy())
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn color(&mut self, color: Color) -> io::Result<()> {
+        self.f32(color.r)?;
+        self.f32(color.g)?;
+        self.f32(color.b)
+    }
+
+    fn bounds(&mut self, bounds: Option<BoundingBox>) -> io::Result<()> {
+        let Some(bounds) = bounds else {
+            return self.u8(0);
+        };
+        self.u8(1)?;
+        for value in [bounds.min, bounds.max]
+            .iter()
+            .flat_map(|v| [v.x, v.y, v.z])
+        {
+            self.f32(value)?;
+        }
+        Ok(())
+    }
+
+    fn range(&mut self, range: &Range<usize>) -> io::Result<()> {
+        self.count(range.start)?;
+        self.count(range.end)
+    }
+
+    fn mesh(&mut self, mesh: &MeshData) -> io::Result<()> {
+        self.count(mesh.triangle_indices)?;
+        self.count(mesh.line_indices)?;
+        self.count(mesh.point_indices)?;
+        self.count(mesh.materials.len())?;
+        for (material, indices) in mesh.materials.iter() {
+            self.optional_index(*material)?;
+            self.range(indices)?;
+        }
+        self.count(mesh.vertices.len())?;
+        self.align()?;
+        for &value in mesh.vertices.iter() {
+            self.f32(value)?;
+        }
+        match &mesh.indices {
+            Elements::U16(indices) => {
+                self.u8(2)?;
+                self.count(indices.len())?;
+                self.align()?;
+                for &index in indices.iter() {
+                    self.bytes(&index.to_le_bytes())?;
+                }
+            }
+            Elements::U32(indices) => {
+                self.u8(4)?;
+                self.count(indices.len())?;
+                self.align()?;
+                for &index in indices.iter() {
+                    self.u32(index)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn scene(&mut self, scene: &Scene) -> io::Result<()> {
+        self.count(scene.vertices.len())?;
+        for v in scene.vertices.iter() {
+            for value in [v.x, v.y, v.z, v.w] {
+                self.f32(value)?;
+            }
+        }
+        self.count(scene.vertex_colors.len())?;
+        for &color in scene.vertex_colors.iter() {
+            for value in color {
+                self.f32(value)?;
+            }
+        }
+        self.count(scene.vertex_properties.len())?;
+        for property in scene.vertex_properties.iter() {
+            self.string(&property.name)?;
+            self.count(property.values.len())?;
+            for &value in property.values.iter() {
+                self.f32(value)?;
+            }
+        }
+        self.count(scene.texture_coordinates.len())?;
+        for vt in scene.texture_coordinates.iter() {
+            for value in [vt.u, vt.v, vt.w] {
+                self.f32(value)?;
+            }
+        }
+        self.count(scene.normals.len())?;
+        for vn in scene.normals.iter() {
+            for value in [vn.x, vn.y, vn.z] {
+                self.f32(value)?;
+            }
+        }
+        self.count(scene.triangles.len())?;
+        for triangle in scene.triangles.iter() {
+            for corner in triangle.corners.iter() {
+                self.index(corner.vertex)?;
+                self.optional_index(corner.texture)?;
+                self.optional_index(corner.normal)?;
+            }
+            self.u32(triangle.smoothing_group)?;
+            self.optional_index(triangle.material)?;
+        }
+        self.count(scene.lines.len())?;
+        for &[a, b] in scene.lines.iter() {
+            self.index(a)?;
+            self.index(b)?;
+        }
+        self.count(scene.points.len())?;
+        for &point in scene.points.iter() {
+            self.index(point)?;
+        }
+        self.count(scene.objects.len())?;
+        for object in scene.objects.iter() {
+            self.string(&object.name)?;
+            self.range(&object.triangles)?;
+            self.count(object.groups.len())?;
+            for group in object.groups.iter() {
+                self.count(group.names.len())?;
+                for name in group.names.iter() {
+                    self.string(name)?;
+                }
+                self.range(&group.triangles)?;
+            }
+        }
+        self.count(scene.materials.len())?;
+        for material in scene.materials.iter() {
+            self.string(&material.name)?;
+            self.color(material.ambient)?;
+            self.color(material.diffuse)?;
+            self.color(material.specular)?;
+            self.color(material.emissive)?;
+            self.f32(material.shininess)?;
+            self.f32(material.optical_density)?;
+            self.f32(material.dissolve)?;
+            self.u32(material.illumination)?;
+            self.path(&material.diffuse_map)?;
+            self.path(&material.specular_map)?;
+            self.path(&material.bump_map)?;
+            self.path(&material.dissolve_map)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,785 @@
+use crate::gltf::{
+    determinant, invert, multiply, transform_normal, transform_point, Matrix4, IDENTITY,
+};
+use crate::json::{object, Value};
+use crate::scene::{Color, FaceVertex, Material, Scene};
+use image::ImageFormat;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, BufWriter, Cursor, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+const CHUNK_JSON: u32 = 0x4E4F534A;
+const CHUNK_BIN: u32 = 0x004E4942;
+
+const ARRAY_BUFFER: usize = 34962;
+const ELEMENT_ARRAY_BUFFER: usize = 34963;
+const UNSIGNED_SHORT: usize = 5123;
+const UNSIGNED_INT: usize = 5125;
+const FLOAT: usize = 5126;
+
+const POINTS: usize = 0;
+const LINES: usize = 1;
+const TRIANGLES: usize = 4;
+
+/// Writes `scenes` to a .glb at `path`, each scene becoming a root node named
+/// after its label, e.g. the file it was loaded from.
+pub fn save(scenes: &[(&str, &Scene)], path: &Path) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    write_glb(scenes, file)
+}
+
+/// Writes `scenes` as binary glTF 2.0. Each object becomes a node with a mesh,
+/// with one primitive per material, and lines and points a last node of their
+/// own. A scene with a node hierarchy keeps it, its objects moved back into
+/// their node's space. Materials are turned into metallic-roughness approximations the
+/// importer maps back to the same values, and their diffuse and bump maps are
+/// embedded. Texture coordinates are flipped to glTF's top left origin.
+pub fn write_glb<W: Write>(scenes: &[(&str, &Scene)], mut out: W) -> io::Result<()> {
+    let mut builder = Builder::default();
+    for (label, scene) in scenes {
+        builder.scene(label, scene);
+    }
+    let (json, mut bin) = builder.finish();
+
+    // Chunks are padded to 4 bytes, JSON with spaces and binary with zeros
+    let mut json = json.into_bytes();
+    json.resize(json.len().div_ceil(4) * 4, b' ');
+    bin.resize(bin.len().div_ceil(4) * 4, 0);
+    let mut length = 12 + 8 + json.len();
+    if !bin.is_empty() {
+        length += 8 + bin.len();
+    }
+    out.write_all(b"glTF")?;
+    out.write_all(&2u32.to_le_bytes())?;
+    out.write_all(&(length as u32).to_le_bytes())?;
+    out.write_all(&(json.len() as u32).to_le_bytes())?;
+    out.write_all(&CHUNK_JSON.to_le_bytes())?;
+    out.write_all(&json)?;
+    if !bin.is_empty() {
+        out.write_all(&(bin.len() as u32).to_le_bytes())?;
+        out.write_all(&CHUNK_BIN.to_le_bytes())?;
+        out.write_all(&bin)?;
+    }
+    out.flush()
+}
+
+// Corners of a primitive, in threes for triangles and twos for lines
+struct Corners {
+    mode: usize,
+    material: Option<usize>,
+    corners: Vec<FaceVertex>,
+}
+
+// A primitive indexing the vertices of its mesh, and whether all its corners
+// have normals and texture coordinates
+struct Primitive {
+    material: Option<usize>,
+    normals: bool,
+    textures: bool,
+    mode: usize,
+    indices: Vec<u32>,
+}
+
+// The document being built and the binary chunk its accessors point into
+#[derive(Default)]
+struct Builder {
+    bin: Vec<u8>,
+    buffer_views: Vec<Value>,
+    accessors: Vec<Value>,
+    meshes: Vec<Value>,
+    nodes: Vec<Value>,
+    roots: Vec<Value>,
+    materials: Vec<Value>,
+    textures: Vec<Value>,
+    images: Vec<Value>,
+    // Texture of each image file, None when it could not be read
+    image_textures: HashMap<PathBuf, Option<usize>>,
+}
+
+impl Builder {
+    fn buffer_view(&mut self, data: &[u8], target: Option<usize>) -> usize {
+        // Accessors need their data aligned to their component size
+        self.bin.resize(self.bin.len().div_ceil(4) * 4, 0);
+        let mut view = vec![
+            ("buffer", 0.into()),
+            ("byteOffset", self.bin.len().into()),
+            ("byteLength", data.len().into()),
+        ];
+        if let Some(target) = target {
+            view.push(("target", target.into()));
+        }
+        self.bin.extend_from_slice(data);
+        self.buffer_views.push(object(view));
+        self.buffer_views.len() - 1
+    }
+
+    // Vertex attribute accessor, with the bounds glTF requires for positions
+    fn floats<const N: usize>(&mut self, values: &[[f32; N]], bounds: bool) -> usize {
+        let data: Vec<u8> = values
+            .iter()
+            .flatten()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let view = self.buffer_view(&data, Some(ARRAY_BUFFER));
+        let mut accessor = vec![
+            ("bufferView", view.into()),
+            ("componentType", FLOAT.into()),
+            ("count", values.len().into()),
+            ("type", ["SCALAR", "VEC2", "VEC3", "VEC4"][N - 1].into()),
+        ];
+        if bounds {
+            let (mut min, mut max) = ([f32::MAX; N], [f32::MIN; N]);
+            for value in values {
+                for i in 0..N {
+                    min[i] = min[i].min(value[i]);
+                    max[i] = max[i].max(value[i]);
+                }
+            }
+            accessor.push(("min", min.map(Value::from).to_vec().into()));
+            accessor.push(("max", max.map(Value::from).to_vec().into()));
+        }
+        self.accessors.push(object(accessor));
+        self.accessors.len() - 1
+    }
+
+    // 16 bit indices when they fit, 65535 being reserved for primitive
+    // restart
+    fn indices(&mut self, indices: &[u32], vertices: usize) -> usize {
+        let (data, component_type): (Vec<u8>, usize) = if vertices < u16::MAX as usize {
+            let data = indices.iter().flat_map(|&i| (i as u16).to_le_bytes());
+            (data.collect(), UNSIGNED_SHORT)
+        } else {
+            let data = indices.iter().flat_map(|&i| i.to_le_bytes());
+            (data.collect(), UNSIGNED_INT)
+        };
+        let view = self.buffer_view(&data, Some(ELEMENT_ARRAY_BUFFER));
+        self.accessors.push(object(vec![
+            ("bufferView", view.into()),
+            ("componentType", component_type.into()),
+            ("count", indices.len().into()),
+            ("type", "SCALAR".into()),
+        ]));
+        self.accessors.len() - 1
+    }
+
+    // PNG and JPEG files are embedded as they are, other formats converted to
+    // PNG. An image that cannot be read leaves the material without texture,
+    // as the viewer does.
+    fn texture(&mut self, path: &Path) -> Option<usize> {
+        if let Some(&texture) = self.image_textures.get(path) {
+            return texture;
+        }
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase());
+        let image = match extension.as_deref() {
+            Some("png") => fs::read(path).map(|data| (data, "image/png")),
+            Some("jpg" | "jpeg") => fs::read(path).map(|data| (data, "image/jpeg")),
+            _ => image::open(path)
+                .and_then(|image| {
+                    let mut data = Cursor::new(Vec::new());
+                    image.write_to(&mut data, ImageFormat::Png)?;
+                    Ok((data.into_inner(), "image/png"))
+                })
+                .map_err(io::Error::other),
+        };
+        let texture = match image {
+            Ok((data, mime_type)) => {
+                let view = self.buffer_view(&data, None);
+                self.images.push(object(vec![
+                    ("bufferView", view.into()),
+                    ("mimeType", mime_type.into()),
+                ]));
+                let source = self.images.len() - 1;
+                self.textures.push(object(vec![("source", source.into())]));
+                Some(self.textures.len() - 1)
+            }
+            Err(e) => {
+                eprintln!("Could not embed {}: {}", path.display(), e);
+                None
+            }
+        };
+        self.image_textures.insert(path.to_path_buf(), texture);
+        texture
+    }
+
+    // The specular color is read as a metalness when it is tinted and lies
+    // between a dielectric's 4% grey and the diffuse color, as the importer
+    // maps metalness back, and as a plain dielectric otherwise, which is what
+    // most MTL files mean by a grey specular. The shininess gives the
+    // roughness. The Phong values themselves are kept in the extras, for the
+    // importer to restore.
+    fn material(&mut self, material: &Material) -> Value {
+        let average = |c: Color| (c.r + c.g + c.b) / 3.;
+        let (diffuse, specular) = (material.diffuse, material.specular);
+        let channels = [
+            (diffuse.r, specular.r),
+            (diffuse.g, specular.g),
+            (diffuse.b, specular.b),
+        ];
+        let tinted = specular.r.max(specular.g).max(specular.b)
+            - specular.r.min(specular.g).min(specular.b)
+            > 0.01;
+        let range = average(diffuse) - 0.04;
+        let mut metallic = match tinted && range.abs() > 1e-6 {
+            true => ((average(specular) - 0.04) / range).clamp(0., 1.),
+            false => 0.,
+        };
+        let lerp = |base: f32| 0.04 + (base - 0.04) * metallic;
+        if channels
+            .iter()
+            .any(|&(base, specular)| (lerp(base) - specular).abs() > 0.01)
+        {
+            metallic = 0.;
+        }
+        let roughness = (2. / (material.shininess.max(0.) + 2.)).powf(0.25);
+
+        let base_color = [diffuse.r, diffuse.g, diffuse.b, material.dissolve];
+        let mut pbr = vec![
+            (
+                "baseColorFactor",
+                base_color.map(Value::from).to_vec().into(),
+            ),
+            ("metallicFactor", metallic.into()),
+            ("roughnessFactor", roughness.into()),
+        ];
+        let texture_info = |index: usize| object(vec![("index", index.into())]);
+        if let Some(index) = material
+            .diffuse_map
+            .as_deref()
+            .and_then(|p| self.texture(p))
+        {
+            pbr.push(("baseColorTexture", texture_info(index)));
+        }
+        let emissive = material.emissive;
+        let emissive = [emissive.r, emissive.g, emissive.b].map(|c| Value::from(c.clamp(0., 1.)));
+        let mut value = vec![
+            ("name", material.name.as_str().into()),
+            ("pbrMetallicRoughness", object(pbr)),
+            ("emissiveFactor", emissive.to_vec().into()),
+            // The viewer does not cull back faces either
+            ("doubleSided", true.into()),
+        ];
+        if let Some(index) = material.bump_map.as_deref().and_then(|p| self.texture(p)) {
+            value.push(("normalTexture", texture_info(index)));
+        }
+        if material.dissolve < 1. {
+            value.push(("alphaMode", "BLEND".into()));
+        }
+        let color = |c: Color| Value::from(vec![c.r.into(), c.g.into(), c.b.into()]);
+        let extras = object(vec![
+            ("ambient", color(material.ambient)),
+            ("specular", color(material.specular)),
+            ("emissive", color(material.emissive)),
+            ("shininess", material.shininess.into()),
+            ("opticalDensity", material.optical_density.into()),
+            ("illumination", (material.illumination as usize).into()),
+        ]);
+        value.push(("extras", extras));
+        object(value)
+    }
+
+    fn scene(&mut self, label: &str, scene: &Scene) {
+        let material_base = self.materials.len();
+        for material in &scene.materials {
+            let material = self.material(material);
+            self.materials.push(material);
+        }
+        let (mut children, placed) = self.hierarchy(scene, material_base);
+        for (object, name, triangles) in parts(scene) {
+            if object.is_some_and(|object| placed[object]) {
+                continue;
+            }
+            let primitives = triangle_primitives(scene, triangles);
+            if let Some(mesh) = self.mesh(scene, name, primitives, material_base, None) {
+                children.push(self.node(name, None, Some(mesh), Vec::new()).into());
+            }
+        }
+        let position_only = |vertex: usize| FaceVertex {
+            vertex,
+            texture: None,
+            normal: None,
+        };
+        let mut primitives = Vec::new();
+        for (mode, corners) in [
+            (LINES, scene.lines.concat()),
+            (POINTS, scene.points.clone()),
+        ] {
+            if !corners.is_empty() {
+                let corners = corners.into_iter().map(position_only).collect();
+                primitives.push(Corners {
+                    mode,
+                    material: None,
+                    corners,
+                });
+            }
+        }
+        if let Some(mesh) = self.mesh(scene, "lines and points", primitives, material_base, None) {
+            children.push(
+                self.node("lines and points", None, Some(mesh), Vec::new())
+                    .into(),
+            );
+        }
+        let root = self.node(label, None, None, children);
+        self.roots.push(root.into());
+    }
+
+    // The root nodes of the scene's hierarchy, and which objects went into
+    // it. Objects whose node flattens space stay in world space, outside.
+    fn hierarchy(&mut self, scene: &Scene, material_base: usize) -> (Vec<Value>, Vec<bool>) {
+        let mut worlds: Vec<Matrix4> = Vec::with_capacity(scene.nodes.len());
+        for node in &scene.nodes {
+            let parent = node.parent.map_or(IDENTITY, |parent| worlds[parent]);
+            worlds.push(multiply(&parent, &node.matrix));
+        }
+        let mut placed = vec![false; scene.objects.len()];
+        let mut children: Vec<Vec<Value>> = vec![Vec::new(); scene.nodes.len()];
+        let mut roots = Vec::new();
+        // Children come after their parent, so building backwards has them
+        // ready in time
+        for (id, node) in scene.nodes.iter().enumerate().rev() {
+            let inverse = invert(&worlds[id]);
+            let mesh = node.object.zip(inverse).and_then(|(index, inverse)| {
+                let object = &scene.objects[index];
+                placed[index] = true;
+                let mut primitives = triangle_primitives(scene, object.triangles.clone());
+                // The importer flips faces under a mirroring node, so they are
+                // flipped ahead of it
+                if determinant(&worlds[id]) < 0. {
+                    for primitive in primitives.iter_mut() {
+                        for triangle in primitive.corners.chunks_exact_mut(3) {
+                            triangle.swap(1, 2);
+                        }
+                    }
+                }
+                let inverse = Some(&inverse).filter(|&inverse| inverse != &IDENTITY);
+                self.mesh(scene, &object.name, primitives, material_base, inverse)
+            });
+            let mut node_children = std::mem::take(&mut children[id]);
+            node_children.reverse();
+            let index = self.node(&node.name, Some(&node.matrix), mesh, node_children);
+            match node.parent {
+                Some(parent) => children[parent].push(index.into()),
+                None => roots.push(index.into()),
+            }
+        }
+        roots.reverse();
+        (roots, placed)
+    }
+
+    fn node(
+        &mut self,
+        name: &str,
+        matrix: Option<&Matrix4>,
+        mesh: Option<usize>,
+        children: Vec<Value>,
+    ) -> usize {
+        let mut node = Vec::new();
+        if !name.is_empty() {
+            node.push(("name", name.into()));
+        }
+        if let Some(matrix) = matrix.filter(|&matrix| matrix != &IDENTITY) {
+            node.push(("matrix", matrix.map(Value::from).to_vec().into()));
+        }
+        if let Some(mesh) = mesh {
+            node.push(("mesh", mesh.into()));
+        }
+        if !children.is_empty() {
+            node.push(("children", children.into()));
+        }
+        self.nodes.push(object(node));
+        self.nodes.len() - 1
+    }
+
+    // A mesh over the vertices its primitives use, each distinct (v, vt, vn)
+    // corner becoming one vertex, moved by `transform` if any
+    fn mesh(
+        &mut self,
+        scene: &Scene,
+        name: &str,
+        primitives: Vec<Corners>,
+        material_base: usize,
+        transform: Option<&Matrix4>,
+    ) -> Option<usize> {
+        if primitives.is_empty() {
+            return None;
+        }
+        let mut vertices: Vec<FaceVertex> = Vec::new();
+        let mut unique: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+        let primitives: Vec<Primitive> = primitives
+            .into_iter()
+            .map(
+                |Corners {
+                     mode,
+                     material,
+                     corners,
+                 }| {
+                    let normals = corners.iter().all(|corner| corner.normal.is_some());
+                    let textures = corners.iter().all(|corner| corner.texture.is_some());
+                    let indices = corners
+                        .iter()
+                        .map(|corner| {
+                            let key = (corner.vertex, corner.texture, corner.normal);
+                            *unique.entry(key).or_insert_with(|| {
+                                vertices.push(*corner);
+                                (vertices.len() - 1) as u32
+                            })
+                        })
+                        .collect();
+                    Primitive {
+                        material,
+                        normals,
+                        textures,
+                        mode,
+                        indices,
+                    }
+                },
+            )
+            .collect();
+
+        let positions: Vec<[f32; 3]> = vertices
+            .iter()
+            .map(|corner| {
+                let v = scene.vertices[corner.vertex];
+                match transform {
+                    Some(transform) => transform_point(transform, [v.x, v.y, v.z]),
+                    None => [v.x, v.y, v.z],
+                }
+            })
+            .collect();
+        let position_accessor = self.floats(&positions, true);
+        // Vertices only used by primitives without normals or texture
+        // coordinates get placeholders
+        let normal_accessor = primitives
+            .iter()
+            .any(|primitive| primitive.normals)
+            .then(|| {
+                let normals: Vec<[f32; 3]> = vertices
+                    .iter()
+                    .map(|corner| match corner.normal {
+                        Some(n) => {
+                            let n = scene.normals[n];
+                            match transform {
+                                Some(transform) => transform_normal(transform, [n.x, n.y, n.z]),
+                                None => [n.x, n.y, n.z],
+                            }
+                        }
+                        None => [0., 0., 1.],
+                    })
+                    .collect();
+                self.floats(&normals, false)
+            });
+        let texture_accessor = primitives
+            .iter()
+            .any(|primitive| primitive.textures)
+            .then(|| {
+                let textures: Vec<[f32; 2]> = vertices
+                    .iter()
+                    .map(|corner| match corner.texture {
+                        Some(t) => {
+                            let t = scene.texture_coordinates[t];
+                            [t.u, 1. - t.v]
+                        }
+                        None => [0., 0.],
+                    })
+                    .collect();
+                self.floats(&textures, false)
+            });
+        let color_accessor = (!scene.vertex_colors.is_empty()).then(|| {
+            let colors: Vec<[f32; 4]> = vertices
+                .iter()
+                .map(|corner| scene.vertex_colors[corner.vertex])
+                .collect();
+            self.floats(&colors, false)
+        });
+
+        let primitives: Vec<Value> = primitives
+            .iter()
+            .map(|primitive| {
+                let mut attributes = vec![("POSITION", position_accessor.into())];
+                if let Some(normals) = normal_accessor.filter(|_| primitive.normals) {
+                    attributes.push(("NORMAL", normals.into()));
+                }
+                if let Some(textures) = texture_accessor.filter(|_| primitive.textures) {
+                    attributes.push(("TEXCOORD_0", textures.into()));
+                }
+                if let Some(colors) = color_accessor {
+                    attributes.push(("COLOR_0", colors.into()));
+                }
+                let indices = self.indices(&primitive.indices, vertices.len());
+                let mut value = vec![
+                    ("attributes", object(attributes)),
+                    ("indices", indices.into()),
+                    ("mode", primitive.mode.into()),
+                ];
+                if let Some(material) = primitive.material {
+                    value.push(("material", (material_base + material).into()));
+                }
+                object(value)
+            })
+            .collect();
+        let mut mesh = vec![("primitives", primitives.into())];
+        if !name.is_empty() {
+            mesh.push(("name", name.into()));
+        }
+        self.meshes.push(object(mesh));
+        Some(self.meshes.len() - 1)
+    }
+
+    // The document, leaving out the arrays glTF forbids to be empty
+    fn finish(self) -> (String, Vec<u8>) {
+        let mut document = vec![
+            (
+                "asset",
+                object(vec![
+                    ("version", "2.0".into()),
+                    ("generator", "scop".into()),
+                ]),
+            ),
+            ("scene", 0.into()),
+            (
+                "scenes",
+                vec![object(vec![("nodes", self.roots.into())])].into(),
+            ),
+        ];
+        let arrays = [
+            ("nodes", self.nodes),
+            ("meshes", self.meshes),
+            ("materials", self.materials),
+            ("textures", self.textures),
+            ("images", self.images),
+            ("accessors", self.accessors),
+            ("bufferViews", self.buffer_views),
+        ];
+        for (name, items) in arrays {
+            if !items.is_empty() {
+                document.push((name, items.into()));
+            }
+        }
+        if !self.bin.is_empty() {
+            let buffer = object(vec![("byteLength", self.bin.len().into())]);
+            document.push(("buffers", vec![buffer].into()));
+        }
+        (object(document).to_string(), self.bin)
+    }
+}
+
+// Triangle ranges of the objects, with their index, and of the triangles
+// outside any object
+fn parts(scene: &Scene) -> Vec<(Option<usize>, &str, Range<usize>)> {
+    let mut objects: Vec<_> = scene.objects.iter().enumerate().collect();
+    objects.sort_by_key(|(_, object)| object.triangles.start);
+    let mut parts = Vec::new();
+    let mut covered = 0;
+    for (index, object) in objects {
+        if object.triangles.start > covered {
+            parts.push((None, "", covered..object.triangles.start));
+        }
+        parts.push((Some(index), object.name.as_str(), object.triangles.clone()));
+        covered = covered.max(object.triangles.end);
+    }
+    if covered < scene.triangles.len() {
+        parts.push((None, "", covered..scene.triangles.len()));
+    }
+    parts.retain(|(_, _, triangles)| !triangles.is_empty());
+    parts
+}
+
+// Triangles split by material, and by whether their corners all have normals
+// and texture coordinates, as glTF attributes apply to whole primitives
+fn triangle_primitives(scene: &Scene, triangles: Range<usize>) -> Vec<Corners> {
+    let mut primitives: Vec<Corners> = Vec::new();
+    let mut keys: Vec<(Option<usize>, bool, bool)> = Vec::new();
+    for triangle in &scene.triangles[triangles] {
+        let normals = triangle.corners.iter().all(|c| c.normal.is_some());
+        let textures = triangle.corners.iter().all(|c| c.texture.is_some());
+        let key = (triangle.material, normals, textures);
+        let i = keys.iter().position(|&k| k == key).unwrap_or_else(|| {
+            keys.push(key);
+            primitives.push(Corners {
+                mode: TRIANGLES,
+                material: triangle.material,
+                corners: Vec::new(),
+            });
+            keys.len() - 1
+        });
+        primitives[i]
+            .corners
+            .extend(triangle.corners.map(|corner| FaceVertex {
+                vertex: corner.vertex,
+                texture: corner.texture.filter(|_| textures),
+                normal: corner.normal.filter(|_| normals),
+            }));
+    }
+    primitives
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gltf::GltfParser;
+    use crate::normals::generate_normals;
+    use crate::parser::Parser;
+    use crate::scene::Node;
+
+    const OBJ: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 0.5 0.5 1
+vt 0 0
+vt 1 0
+vt 1 1
+o first
+usemtl red
+f 1/1 2/2 3/3 4/1
+usemtl blue
+f 1 3 5
+o second
+f 2/1 3/2 5/3
+";
+
+    // Exports `scene`, then imports the file back
+    fn round_trip(scene: &Scene, name: &str) -> Scene {
+        let path = std::env::temp_dir().join(format!("scop-{}-{}.glb", name, std::process::id()));
+        save(&[(name, scene)], &path).unwrap();
+        let imported = GltfParser::new(path.to_str().unwrap())
+            .and_then(GltfParser::generate_scene)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        imported
+    }
+
+    // Each triangle as its corner positions and material factors, in a stable
+    // order since primitives regroup triangles by material
+    fn corners(scene: &Scene) -> Vec<String> {
+        let mut corners: Vec<_> = scene
+            .triangles
+            .iter()
+            .map(|triangle| {
+                let positions = triangle.corners.map(|corner| {
+                    let v = scene.vertices[corner.vertex];
+                    format!("{:.4} {:.4} {:.4}", v.x, v.y, v.z)
+                });
+                let material = triangle.material.map(|m| {
+                    let m = &scene.materials[m];
+                    format!(
+                        "{} kd {:.4} {:.4} {:.4} ks {:.4} {:.4} {:.4} ns {:.2} d {:.4}",
+                        m.name,
+                        m.diffuse.r,
+                        m.diffuse.g,
+                        m.diffuse.b,
+                        m.specular.r,
+                        m.specular.g,
+                        m.specular.b,
+                        m.shininess,
+                        m.dissolve
+                    )
+                });
+                format!("{:?} {:?}", positions, material)
+            })
+            .collect();
+        corners.sort();
+        corners
+    }
+
+    fn assert_round_trip(mut scene: Scene, name: &str) {
+        generate_normals(&mut scene, Default::default());
+        let imported = round_trip(&scene, name);
+        assert_eq!(imported.triangles.len(), scene.triangles.len());
+        assert_eq!(corners(&imported), corners(&scene));
+    }
+
+    #[test]
+    fn round_trip_keeps_positions_and_materials() {
+        let mut scene = Parser::from_reader(OBJ.as_bytes(), "<test>")
+            .generate_scene()
+            .unwrap();
+        // A tinted specular becomes metalness, a grey one stays dielectric
+        let red = &mut scene.materials[0];
+        red.diffuse = Color::new(0.9, 0.1, 0.1);
+        red.specular = Color::new(0.5, 0.3, 0.1);
+        red.shininess = 200.;
+        let blue = &mut scene.materials[1];
+        blue.diffuse = Color::new(0.1, 0.2, 0.8);
+        blue.specular = Color::new(0.25, 0.25, 0.25);
+        blue.dissolve = 0.5;
+        assert_round_trip(scene, "materials");
+    }
+
+    #[test]
+    fn round_trip_keeps_the_default_model() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/resources/42.obj");
+        let scene = Parser::new(path).unwrap().generate_scene().unwrap();
+        assert_round_trip(scene, "42");
+    }
+
+    #[test]
+    fn round_trip_keeps_the_node_hierarchy() {
+        let mut scene = Parser::from_reader(OBJ.as_bytes(), "<test>")
+            .generate_scene()
+            .unwrap();
+        generate_normals(&mut scene, Default::default());
+        // A quarter turn around z and a move, then a mirror under it
+        let turn = [
+            0., 1., 0., 0., -1., 0., 0., 0., 0., 0., 1., 0., 1., 2., 3., 1.,
+        ];
+        let mirror = [
+            -1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.,
+        ];
+        scene.nodes = vec![
+            Node {
+                name: "arm".to_string(),
+                parent: None,
+                matrix: turn,
+                object: None,
+            },
+            Node {
+                name: "hand".to_string(),
+                parent: Some(0),
+                matrix: mirror,
+                object: Some(0),
+            },
+        ];
+        let imported = round_trip(&scene, "hierarchy");
+
+        // Under the node of the scene's label
+        let nodes: Vec<_> = imported
+            .nodes
+            .iter()
+            .map(|node| (node.name.as_str(), node.parent))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                ("hierarchy", None),
+                ("arm", Some(0)),
+                ("hand", Some(1)),
+                ("second", Some(0)),
+            ]
+        );
+        assert_eq!(imported.nodes[1].matrix, turn);
+        assert_eq!(imported.nodes[2].matrix, mirror);
+        let hand = imported.nodes[2].object.unwrap();
+        assert_eq!(imported.objects[hand].name, "hierarchy/arm/hand");
+
+        // Moved into the node's space and back, in the same winding
+        assert_eq!(imported.triangles.len(), scene.triangles.len());
+        for (a, b) in scene.triangles.iter().zip(imported.triangles.iter()) {
+            for (a, b) in a.corners.iter().zip(b.corners.iter()) {
+                let (p, q) = (scene.vertices[a.vertex], imported.vertices[b.vertex]);
+                assert!((p.x - q.x).abs() + (p.y - q.y).abs() + (p.z - q.z).abs() < 1e-5);
+                let (m, n) = (
+                    scene.normals[a.normal.unwrap()],
+                    imported.normals[b.normal.unwrap()],
+                );
+                assert!((m.x - n.x).abs() + (m.y - n.y).abs() + (m.z - n.z).abs() < 1e-5);
+            }
+        }
+    }
+}
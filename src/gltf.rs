@@ -0,0 +1,1110 @@
+use crate::json::{self, Value};
+use crate::parser::{ParseError, ParseErrorKind};
+use crate::scene::{
+    Color, FaceVertex, Group, Material, Node, Normal, Object, Scene, TextureCoordinate, Triangle,
+    Vertex,
+};
+use crate::tuple::{cross, dot, normalize, vector, Vector};
+use std::{
+    collections::HashMap,
+    env, fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const CHUNK_JSON: u32 = 0x4E4F534A;
+const CHUNK_BIN: u32 = 0x004E4942;
+// Largest accessor without a buffer view, whose values are all zero
+const MAX_EMPTY_COUNT: usize = 1 << 24;
+
+/// glTF 2.0 parser for .gltf files, with external or embedded buffers, and
+/// .glb files. Node transforms are baked into the vertices: each node with a
+/// mesh becomes an object named after its path in the hierarchy, and each
+/// primitive a group. The hierarchy itself is kept in `Scene::nodes`. PBR
+/// materials are approximated by `Material`, and embedded images are
+/// extracted to a temporary directory so that texture maps are always files.
+pub struct GltfParser {
+    path: String,
+    directory: PathBuf,
+    data: Vec<u8>,
+}
+
+// Column-major 4x4 matrix, as glTF stores them
+pub type Matrix4 = [f32; 16];
+
+pub const IDENTITY: Matrix4 = [
+    1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1., 0., 0., 0., 0., 1.,
+];
+
+pub fn multiply(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+    let mut m = [0.; 16];
+    for column in 0..4 {
+        for row in 0..4 {
+            m[column * 4 + row] = (0..4).map(|k| a[k * 4 + row] * b[column * 4 + k]).sum();
+        }
+    }
+    m
+}
+
+// Translation, rotation quaternion (x, y, z, w) and scale
+fn from_trs(t: [f32; 3], [x, y, z, w]: [f32; 4], s: [f32; 3]) -> Matrix4 {
+    [
+        (1. - 2. * (y * y + z * z)) * s[0],
+        2. * (x * y + z * w) * s[0],
+        2. * (x * z - y * w) * s[0],
+        0.,
+        2. * (x * y - z * w) * s[1],
+        (1. - 2. * (x * x + z * z)) * s[1],
+        2. * (y * z + x * w) * s[1],
+        0.,
+        2. * (x * z + y * w) * s[2],
+        2. * (y * z - x * w) * s[2],
+        (1. - 2. * (x * x + y * y)) * s[2],
+        0.,
+        t[0],
+        t[1],
+        t[2],
+        1.,
+    ]
+}
+
+fn column(m: &Matrix4, i: usize) -> Vector {
+    vector(m[i * 4], m[i * 4 + 1], m[i * 4 + 2])
+}
+
+pub fn transform_point(m: &Matrix4, p: [f32; 3]) -> [f32; 3] {
+    let v = column(m, 0) * p[0] + column(m, 1) * p[1] + column(m, 2) * p[2] + column(m, 3);
+    [v.x, v.y, v.z]
+}
+
+// Normals go through the inverse transpose, which up to a positive factor is
+// the cofactor matrix times the sign of the determinant
+pub fn transform_normal(m: &Matrix4, n: [f32; 3]) -> [f32; 3] {
+    if m == &IDENTITY {
+        return n;
+    }
+    let (c0, c1, c2) = (column(m, 0), column(m, 1), column(m, 2));
+    let sign = determinant(m).signum();
+    let v = (cross(c1, c2) * n[0] + cross(c2, c0) * n[1] + cross(c0, c1) * n[2]) * sign;
+    let v = if v == vector(0., 0., 0.) {
+        v
+    } else {
+        normalize(v)
+    };
+    [v.x, v.y, v.z]
+}
+
+pub fn determinant(m: &Matrix4) -> f32 {
+    dot(column(m, 0), cross(column(m, 1), column(m, 2)))
+}
+
+// Inverse of an affine transform, None when it flattens space
+pub fn invert(m: &Matrix4) -> Option<Matrix4> {
+    let d = determinant(m);
+    if d.abs() < f32::EPSILON {
+        return None;
+    }
+    // The rows of the inverse are the cross products of the columns
+    let (c0, c1, c2) = (column(m, 0), column(m, 1), column(m, 2));
+    let rows = [cross(c1, c2) / d, cross(c2, c0) / d, cross(c0, c1) / d];
+    let t = column(m, 3);
+    let mut inverse = IDENTITY;
+    for (i, row) in rows.iter().enumerate() {
+        inverse[i] = row.x;
+        inverse[4 + i] = row.y;
+        inverse[8 + i] = row.z;
+        inverse[12 + i] = -dot(*row, t);
+    }
+    Some(inverse)
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    };
+    let digits: Vec<u8> = text
+        .trim_end_matches('=')
+        .bytes()
+        .map(value)
+        .collect::<Option<_>>()?;
+    let mut bytes = Vec::with_capacity(digits.len() * 3 / 4);
+    for chunk in digits.chunks(4) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &d)| bits | (d as u32) << (18 - 6 * i));
+        let decoded = [(bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+        bytes.extend_from_slice(&decoded[..chunk.len().saturating_sub(1)]);
+    }
+    Some(bytes)
+}
+
+// Relative URIs may escape spaces and the like
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Decodes one component, normalizing integers to [0, 1] or [-1, 1] if asked
+type Decode = fn(&[u8], bool) -> f64;
+
+// Values of an accessor, `components` per element
+struct Data {
+    values: Vec<f64>,
+    components: usize,
+}
+
+// Attributes already imported for the current mesh, since the primitives of a
+// mesh commonly share their vertex data: the scene vertices of positions
+// keyed with their colors, and where normals and texture coordinates start,
+// keyed with the vertex count.
+#[derive(Default)]
+struct Shared {
+    vertices: HashMap<(usize, Option<usize>), Vec<usize>>,
+    normals: HashMap<(usize, usize), usize>,
+    textures: HashMap<(usize, usize), usize>,
+}
+
+// State of one import, borrowing the parsed document
+struct Importer<'a> {
+    parser: &'a GltfParser,
+    document: &'a Value,
+    buffers: Vec<Vec<u8>>,
+    images: Vec<Option<PathBuf>>,
+    scene: Scene,
+    has_colors: bool,
+    // Scene vertex of each position and color
+    welded: HashMap<([u32; 3], [u32; 4]), usize>,
+}
+
+impl GltfParser {
+    pub fn new(filename: &str) -> Result<Self, ParseError> {
+        let data = fs::read(filename).map_err(|e| ParseError::io(filename, 0, e))?;
+        let directory = Path::new(filename).parent().unwrap_or(Path::new(""));
+        Ok(Self {
+            path: filename.to_string(),
+            directory: directory.to_path_buf(),
+            data,
+        })
+    }
+
+    // Errors are not tied to a line, `location` says which part of the
+    // document is wrong
+    fn error(&self, kind: ParseErrorKind, location: &str) -> ParseError {
+        ParseError {
+            path: self.path.clone(),
+            line: 0,
+            column: 0,
+            token: location.to_string(),
+            kind,
+        }
+    }
+
+    pub fn generate_scene(self) -> Result<Scene, ParseError> {
+        let (text, bin) = self.split_glb()?;
+        let text = std::str::from_utf8(text)
+            .map_err(|_| self.error(ParseErrorKind::Expected("UTF-8".to_string()), "JSON"))?;
+        let document = json::parse(text, &self.path)?;
+        let version = document
+            .get("asset")
+            .and_then(|asset| asset.get("version"))
+            .and_then(Value::as_str);
+        if !version.is_some_and(|version| version.starts_with("2.")) {
+            let kind = ParseErrorKind::Unsupported("glTF version".to_string());
+            return Err(self.error(kind, version.unwrap_or("asset.version")));
+        }
+        let buffers = self.load_buffers(&document, bin)?;
+        let mut importer = Importer {
+            parser: &self,
+            document: &document,
+            buffers,
+            images: Vec::new(),
+            scene: Scene::new(),
+            has_colors: false,
+            welded: HashMap::new(),
+        };
+        importer.images = importer.extract_images()?;
+        importer.materials()?;
+        importer.nodes()?;
+        let mut scene = importer.scene;
+        if !importer.has_colors {
+            scene.vertex_colors.clear();
+        }
+        Ok(scene)
+    }
+
+    // The JSON and binary chunks of a .glb, or the whole file for a .gltf.
+    // The layout is a 12 byte header (magic, version, length), then chunks
+    // made of their length, type and data.
+    fn split_glb(&self) -> Result<(&[u8], Option<&[u8]>), ParseError> {
+        if !self.data.starts_with(GLB_MAGIC) {
+            return Ok((&self.data, None));
+        }
+        let word = |offset: usize| {
+            self.data
+                .get(offset..offset + 4)
+                .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        };
+        let truncated = || {
+            let kind = ParseErrorKind::MissingComponent("data, the file ended early".to_string());
+            self.error(kind, "GLB chunk")
+        };
+        if word(4) != Some(2) {
+            let kind = ParseErrorKind::Unsupported("GLB version".to_string());
+            return Err(self.error(kind, "GLB header"));
+        }
+        let mut chunks = Vec::new();
+        let mut offset = 12;
+        while offset < self.data.len() {
+            let length = word(offset).ok_or_else(truncated)? as usize;
+            let kind = word(offset + 4).ok_or_else(truncated)?;
+            let data = self
+                .data
+                .get(offset + 8..offset + 8 + length)
+                .ok_or_else(truncated)?;
+            chunks.push((kind, data));
+            // Chunks are padded to 4 bytes
+            offset += 8 + length.div_ceil(4) * 4;
+        }
+        let json = match chunks.first() {
+            Some(&(CHUNK_JSON, data)) => data,
+            _ => {
+                let kind = ParseErrorKind::MissingComponent("JSON chunk".to_string());
+                return Err(self.error(kind, "GLB"));
+            }
+        };
+        let bin = chunks
+            .iter()
+            .find(|(kind, _)| *kind == CHUNK_BIN)
+            .map(|&(_, data)| data);
+        Ok((json, bin))
+    }
+
+    // Data URIs are decoded, other URIs are files next to the .gltf, and a
+    // first buffer without URI is the binary chunk of a .glb
+    fn read_uri(&self, uri: &str, location: &str) -> Result<Vec<u8>, ParseError> {
+        if let Some(data) = uri.strip_prefix("data:") {
+            let base64 = data.split_once(";base64,").map(|(_, base64)| base64);
+            return base64.and_then(decode_base64).ok_or_else(|| {
+                self.error(
+                    ParseErrorKind::Unsupported("data URI".to_string()),
+                    location,
+                )
+            });
+        }
+        let path = self.directory.join(decode_uri(uri));
+        fs::read(&path).map_err(|e| ParseError::io(&path.to_string_lossy(), 0, e))
+    }
+
+    fn load_buffers(
+        &self,
+        document: &Value,
+        bin: Option<&[u8]>,
+    ) -> Result<Vec<Vec<u8>>, ParseError> {
+        let mut buffers = Vec::new();
+        for (i, buffer) in document.items("buffers").iter().enumerate() {
+            let location = format!("buffers[{}]", i);
+            let data = match buffer.get("uri").and_then(Value::as_str) {
+                Some(uri) => self.read_uri(uri, &location)?,
+                None => match bin {
+                    Some(bin) if i == 0 => bin.to_vec(),
+                    _ => {
+                        let kind = ParseErrorKind::MissingComponent("buffer data".to_string());
+                        return Err(self.error(kind, &location));
+                    }
+                },
+            };
+            let length = buffer.get("byteLength").and_then(Value::as_usize);
+            if length.is_none_or(|length| length > data.len()) {
+                let kind = ParseErrorKind::MissingComponent("data, buffer too short".to_string());
+                return Err(self.error(kind, &location));
+            }
+            buffers.push(data);
+        }
+        Ok(buffers)
+    }
+}
+
+impl<'a> Importer<'a> {
+    fn error(&self, kind: ParseErrorKind, location: &str) -> ParseError {
+        self.parser.error(kind, location)
+    }
+
+    // Item `index` of the top level array `name`
+    fn item(&self, name: &str, index: usize) -> Result<&'a Value, ParseError> {
+        self.document.items(name).get(index).ok_or_else(|| {
+            self.error(
+                ParseErrorKind::IndexOutOfRange,
+                &format!("{}[{}]", name, index),
+            )
+        })
+    }
+
+    fn index(&self, value: &Value, key: &str, location: &str) -> Result<Option<usize>, ParseError> {
+        match value.get(key) {
+            None => Ok(None),
+            Some(index) => index.as_usize().map(Some).ok_or_else(|| {
+                let kind = ParseErrorKind::Expected("index".to_string());
+                self.error(kind, &format!("{}.{}", location, key))
+            }),
+        }
+    }
+
+    // Numbers of the array at `key`, `default` when it is missing
+    fn floats<const N: usize>(
+        &self,
+        value: Option<&Value>,
+        key: &str,
+        default: [f32; N],
+    ) -> [f32; N] {
+        let Some(items) = value
+            .and_then(|value| value.get(key))
+            .and_then(Value::as_array)
+        else {
+            return default;
+        };
+        let mut floats = default;
+        for (float, item) in floats.iter_mut().zip(items) {
+            *float = item.as_f64().unwrap_or(0.) as f32;
+        }
+        floats
+    }
+
+    fn float(&self, value: Option<&Value>, key: &str, default: f32) -> f32 {
+        value
+            .and_then(|value| value.get(key))
+            .and_then(Value::as_f64)
+            .map_or(default, |float| float as f32)
+    }
+
+    // Slice of a buffer view, with its stride when interleaved
+    fn buffer_view(&self, index: usize) -> Result<(&[u8], Option<usize>), ParseError> {
+        let location = format!("bufferViews[{}]", index);
+        let view = self.item("bufferViews", index)?;
+        let buffer = self.index(view, "buffer", &location)?.unwrap_or(0);
+        let buffer = self
+            .buffers
+            .get(buffer)
+            .ok_or_else(|| self.error(ParseErrorKind::IndexOutOfRange, &location))?;
+        let offset = self.index(view, "byteOffset", &location)?.unwrap_or(0);
+        let length = self.index(view, "byteLength", &location)?.unwrap_or(0);
+        let stride = self.index(view, "byteStride", &location)?;
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| buffer.get(offset..end))
+            .ok_or_else(|| self.error(ParseErrorKind::IndexOutOfRange, &location))?;
+        Ok((data, stride))
+    }
+
+    fn accessor(&self, index: usize) -> Result<Data, ParseError> {
+        let location = format!("accessors[{}]", index);
+        let accessor = self.item("accessors", index)?;
+        if accessor.get("sparse").is_some() {
+            let kind = ParseErrorKind::Unsupported("sparse accessor".to_string());
+            return Err(self.error(kind, &location));
+        }
+        let count = self.index(accessor, "count", &location)?.unwrap_or(0);
+        let components = match accessor.get("type").and_then(Value::as_str) {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") | Some("MAT2") => 4,
+            Some("MAT3") => 9,
+            Some("MAT4") => 16,
+            _ => {
+                let kind = ParseErrorKind::Unsupported("accessor type".to_string());
+                return Err(self.error(kind, &location));
+            }
+        };
+        let normalized = matches!(accessor.get("normalized"), Some(Value::Bool(true)));
+        let component_type = accessor.get("componentType").and_then(Value::as_usize);
+        let (size, decode): (usize, Decode) = match component_type {
+            Some(5120) => (1, |b, n| {
+                let v = b[0] as i8 as f64;
+                if n {
+                    (v / 127.).max(-1.)
+                } else {
+                    v
+                }
+            }),
+            Some(5121) => (1, |b, n| {
+                let v = b[0] as f64;
+                if n {
+                    v / 255.
+                } else {
+                    v
+                }
+            }),
+            Some(5122) => (2, |b, n| {
+                let v = i16::from_le_bytes([b[0], b[1]]) as f64;
+                if n {
+                    (v / 32767.).max(-1.)
+                } else {
+                    v
+                }
+            }),
+            Some(5123) => (2, |b, n| {
+                let v = u16::from_le_bytes([b[0], b[1]]) as f64;
+                if n {
+                    v / 65535.
+                } else {
+                    v
+                }
+            }),
+            Some(5125) => (4, |b, _| {
+                u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64
+            }),
+            Some(5126) => (4, |b, _| {
+                f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64
+            }),
+            _ => {
+                let kind = ParseErrorKind::Unsupported("component type".to_string());
+                return Err(self.error(kind, &location));
+            }
+        };
+        let Some(view) = self.index(accessor, "bufferView", &location)? else {
+            // Without a view, every value is zero
+            if count > MAX_EMPTY_COUNT {
+                return Err(self.error(ParseErrorKind::BadNumber, &location));
+            }
+            return Ok(Data {
+                values: vec![0.; count * components],
+                components,
+            });
+        };
+        let (data, stride) = self.buffer_view(view)?;
+        let offset = self.index(accessor, "byteOffset", &location)?.unwrap_or(0);
+        let element = size * components;
+        let stride = stride.unwrap_or(element);
+        if stride < element {
+            return Err(self.error(ParseErrorKind::BadNumber, &location));
+        }
+        // The last element must end inside the view, without overflowing
+        let end = match count.checked_sub(1) {
+            None => Some(0),
+            Some(last) => last
+                .checked_mul(stride)
+                .and_then(|start| start.checked_add(offset))
+                .and_then(|start| start.checked_add(element)),
+        };
+        if end.is_none_or(|end| end > data.len()) {
+            let kind = ParseErrorKind::MissingComponent("data, buffer view too short".to_string());
+            return Err(self.error(kind, &location));
+        }
+        let mut values = Vec::with_capacity(count * components);
+        for i in 0..count {
+            let start = offset + i * stride;
+            for component in data[start..start + element].chunks_exact(size) {
+                values.push(decode(component, normalized));
+            }
+        }
+        Ok(Data { values, components })
+    }
+
+    // Images embedded in buffers or data URIs are written to a temporary
+    // directory, unique per source file
+    fn extract_images(&self) -> Result<Vec<Option<PathBuf>>, ParseError> {
+        let mut hasher = DefaultHasher::new();
+        fs::canonicalize(&self.parser.path)
+            .unwrap_or_else(|_| PathBuf::from(&self.parser.path))
+            .hash(&mut hasher);
+        let directory = env::temp_dir().join(format!("scop-{:016x}", hasher.finish()));
+
+        let mut images = Vec::new();
+        for (i, image) in self.document.items("images").iter().enumerate() {
+            let location = format!("images[{}]", i);
+            let uri = image.get("uri").and_then(Value::as_str);
+            if let Some(uri) = uri.filter(|uri| !uri.starts_with("data:")) {
+                images.push(Some(self.parser.directory.join(decode_uri(uri))));
+                continue;
+            }
+            let data = match (uri, self.index(image, "bufferView", &location)?) {
+                (Some(uri), _) => self.parser.read_uri(uri, &location)?,
+                (None, Some(view)) => self.buffer_view(view)?.0.to_vec(),
+                (None, None) => {
+                    let kind = ParseErrorKind::MissingComponent("image data".to_string());
+                    return Err(self.error(kind, &location));
+                }
+            };
+            let mime = image.get("mimeType").and_then(Value::as_str).or(uri);
+            let extension = match mime {
+                Some(mime) if mime.contains("image/jpeg") => "jpg",
+                _ => "png",
+            };
+            let path = directory.join(format!("image{}.{}", i, extension));
+            // A texture that cannot be extracted is only missing, like a
+            // texture file that cannot be found
+            match fs::create_dir_all(&directory).and_then(|_| fs::write(&path, data)) {
+                Ok(()) => images.push(Some(path)),
+                Err(e) => {
+                    eprintln!("Could not extract {}: {}", location, e);
+                    images.push(None);
+                }
+            }
+        }
+        Ok(images)
+    }
+
+    // File of the image behind the texture info `info`, e.g. a material's
+    // baseColorTexture
+    fn texture(&self, info: Option<&Value>, location: &str) -> Result<Option<PathBuf>, ParseError> {
+        let Some(info) = info else {
+            return Ok(None);
+        };
+        let Some(texture) = self.index(info, "index", location)? else {
+            return Ok(None);
+        };
+        let texture = self.item("textures", texture)?;
+        let Some(source) = self.index(texture, "source", location)? else {
+            return Ok(None);
+        };
+        let image = self
+            .images
+            .get(source)
+            .ok_or_else(|| self.error(ParseErrorKind::IndexOutOfRange, location))?;
+        Ok(image.clone())
+    }
+
+    // Metallic-roughness PBR mapped to Phong: the base color becomes the
+    // diffuse color, the specular color goes from a dielectric's 4% grey to
+    // the base color as the metalness rises, and the roughness sets the
+    // shininess through the usual Beckmann to Blinn-Phong exponent 2 / a^2 - 2
+    fn materials(&mut self) -> Result<(), ParseError> {
+        for (i, value) in self.document.items("materials").iter().enumerate() {
+            let location = format!("materials[{}]", i);
+            let mut name = match value.get("name").and_then(Value::as_str) {
+                Some(name) if !name.is_empty() => name.to_string(),
+                _ => format!("material{}", i),
+            };
+            // Names must be unique for the scene to keep them apart
+            if self.scene.material_index(&name).is_some() {
+                name = format!("{}.{}", name, i);
+            }
+            let mut material = Material::new(&name);
+            let pbr = value.get("pbrMetallicRoughness");
+            let [r, g, b, a] = self.floats(pbr, "baseColorFactor", [1., 1., 1., 1.]);
+            let metallic = self.float(pbr, "metallicFactor", 1.).clamp(0., 1.);
+            let roughness = self.float(pbr, "roughnessFactor", 1.).clamp(0.01, 1.);
+            let specular = |base: f32| 0.04 + (base - 0.04) * metallic;
+            material.diffuse = Color::new(r, g, b);
+            material.ambient = Color::new(r * 0.2, g * 0.2, b * 0.2);
+            material.specular = Color::new(specular(r), specular(g), specular(b));
+            material.shininess = (2. / roughness.powi(4) - 2.).clamp(0., 1000.);
+            let [r, g, b] = self.floats(Some(value), "emissiveFactor", [0., 0., 0.]);
+            material.emissive = Color::new(r, g, b);
+            let blended = value.get("alphaMode").and_then(Value::as_str) == Some("BLEND");
+            material.dissolve = if blended { a } else { 1. };
+            let base_texture = pbr.and_then(|pbr| pbr.get("baseColorTexture"));
+            material.diffuse_map = self.texture(base_texture, &location)?;
+            material.bump_map = self.texture(value.get("normalTexture"), &location)?;
+            // Files written by scop keep the original Phong values as well
+            if let Some(extras) = value.get("extras") {
+                let color = |key: &str, color: Color| {
+                    let [r, g, b] = self.floats(Some(extras), key, [color.r, color.g, color.b]);
+                    Color::new(r, g, b)
+                };
+                material.ambient = color("ambient", material.ambient);
+                material.specular = color("specular", material.specular);
+                material.emissive = color("emissive", material.emissive);
+                material.shininess = self.float(Some(extras), "shininess", material.shininess);
+                material.optical_density =
+                    self.float(Some(extras), "opticalDensity", material.optical_density);
+                let illumination = extras.get("illumination").and_then(Value::as_usize);
+                material.illumination = illumination.map_or(material.illumination, |i| i as u32);
+            }
+            self.scene.add_material(material);
+        }
+        Ok(())
+    }
+
+    // Walks the default scene, or every root node when there is none
+    fn nodes(&mut self) -> Result<(), ParseError> {
+        let scene = match self.document.get("scene") {
+            Some(scene) => Some(scene.as_usize().ok_or_else(|| {
+                self.error(ParseErrorKind::Expected("index".to_string()), "scene")
+            })?),
+            None => (!self.document.items("scenes").is_empty()).then_some(0),
+        };
+        let roots: Vec<usize> = match scene {
+            Some(scene) => {
+                let scene = self.item("scenes", scene)?;
+                scene
+                    .items("nodes")
+                    .iter()
+                    .filter_map(Value::as_usize)
+                    .collect()
+            }
+            None => {
+                let nodes = self.document.items("nodes");
+                let children: Vec<usize> = nodes
+                    .iter()
+                    .flat_map(|node| node.items("children"))
+                    .filter_map(Value::as_usize)
+                    .collect();
+                (0..nodes.len()).filter(|i| !children.contains(i)).collect()
+            }
+        };
+        for root in roots {
+            self.node(root, None, &IDENTITY, "", 0)?;
+        }
+        Ok(())
+    }
+
+    fn node(
+        &mut self,
+        index: usize,
+        parent: Option<usize>,
+        parent_world: &Matrix4,
+        path: &str,
+        depth: usize,
+    ) -> Result<(), ParseError> {
+        let location = format!("nodes[{}]", index);
+        // Deeper than the number of nodes means a node is its own ancestor
+        if depth > self.document.items("nodes").len() {
+            let kind = ParseErrorKind::Unsupported("cycle in the node hierarchy".to_string());
+            return Err(self.error(kind, &location));
+        }
+        let node = self.item("nodes", index)?;
+        let local = match node.get("matrix") {
+            Some(_) => self.floats(Some(node), "matrix", IDENTITY),
+            None => from_trs(
+                self.floats(Some(node), "translation", [0., 0., 0.]),
+                self.floats(Some(node), "rotation", [0., 0., 0., 1.]),
+                self.floats(Some(node), "scale", [1., 1., 1.]),
+            ),
+        };
+        let world = multiply(parent_world, &local);
+        let name = match node.get("name").and_then(Value::as_str) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => format!("node{}", index),
+        };
+        let id = self.scene.nodes.len();
+        self.scene.nodes.push(Node {
+            name: name.clone(),
+            parent,
+            matrix: local,
+            object: None,
+        });
+        let path = match path {
+            "" => name,
+            _ => format!("{}/{}", path, name),
+        };
+        if let Some(mesh) = self.index(node, "mesh", &location)? {
+            self.scene.nodes[id].object = self.mesh(mesh, &world, &path)?;
+        }
+        for child in node.items("children") {
+            let child = child.as_usize().ok_or_else(|| {
+                self.error(ParseErrorKind::Expected("index".to_string()), &location)
+            })?;
+            self.node(child, Some(id), &world, &path, depth + 1)?;
+        }
+        Ok(())
+    }
+
+    // The index of the object the mesh became, if it has triangles
+    fn mesh(
+        &mut self,
+        index: usize,
+        world: &Matrix4,
+        path: &str,
+    ) -> Result<Option<usize>, ParseError> {
+        let mesh = self.item("meshes", index)?;
+        let name = match mesh.get("name").and_then(Value::as_str) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => format!("mesh{}", index),
+        };
+        let primitives = mesh.items("primitives");
+        let mut object = Object::new(path, self.scene.triangles.len());
+        let mut shared = Shared::default();
+        for (i, primitive) in primitives.iter().enumerate() {
+            let location = format!("meshes[{}].primitives[{}]", index, i);
+            let start = self.scene.triangles.len();
+            self.primitive(primitive, world, &mut shared, &location)?;
+            let group = match primitives.len() {
+                1 => name.clone(),
+                _ => format!("{}.{}", name, i),
+            };
+            let mut group = Group::new(vec![group], start);
+            group.triangles.end = self.scene.triangles.len();
+            if !group.triangles.is_empty() {
+                object.groups.push(group);
+            }
+        }
+        object.triangles.end = self.scene.triangles.len();
+        if object.is_empty() {
+            return Ok(None);
+        }
+        self.scene.objects.push(object);
+        Ok(Some(self.scene.objects.len() - 1))
+    }
+
+    fn attribute(
+        &self,
+        primitive: &Value,
+        name: &str,
+        location: &str,
+    ) -> Result<Option<usize>, ParseError> {
+        match primitive.get("attributes") {
+            Some(attributes) => self.index(attributes, name, location),
+            None => Ok(None),
+        }
+    }
+
+    // Values of an attribute accessor, checked to have `count` elements of at
+    // least `components` values when a count is given
+    fn attribute_data(
+        &self,
+        accessor: usize,
+        components: usize,
+        count: Option<usize>,
+        location: &str,
+    ) -> Result<Data, ParseError> {
+        let data = self.accessor(accessor)?;
+        let elements = data.values.len() / data.components;
+        if data.components < components || count.is_some_and(|count| count != elements) {
+            let kind = ParseErrorKind::MissingComponent("values in attribute".to_string());
+            return Err(self.error(kind, location));
+        }
+        Ok(data)
+    }
+
+    // Scene vertices of the positions and their colors. glTF splits vertices
+    // wherever a normal or texture coordinate changes, so those that end up
+    // at the same place with the same color are welded back together.
+    fn vertices(
+        &mut self,
+        positions: usize,
+        colors: Option<usize>,
+        world: &Matrix4,
+        location: &str,
+    ) -> Result<Vec<usize>, ParseError> {
+        let positions = self.attribute_data(positions, 3, None, location)?;
+        if positions.components != 3 {
+            let kind = ParseErrorKind::Unsupported("POSITION type".to_string());
+            return Err(self.error(kind, location));
+        }
+        let count = positions.values.len() / 3;
+        let colors = match colors {
+            Some(colors) => Some(self.attribute_data(colors, 3, Some(count), location)?),
+            None => None,
+        };
+        // Vertices of primitives without colors are white, and the colors
+        // are dropped altogether if no primitive has any
+        self.has_colors |= colors.is_some();
+        let mut vertices = Vec::with_capacity(count);
+        for (i, p) in positions.values.chunks_exact(3).enumerate() {
+            let p = transform_point(world, [p[0] as f32, p[1] as f32, p[2] as f32]);
+            let color = match &colors {
+                Some(colors) => {
+                    let c = &colors.values[i * colors.components..(i + 1) * colors.components];
+                    let alpha = c.get(3).copied().unwrap_or(1.);
+                    [c[0], c[1], c[2], alpha].map(|value| value as f32)
+                }
+                None => [1.; 4],
+            };
+            // -0.0 and 0.0 weld together
+            let key = (p.map(|c| (c + 0.).to_bits()), color.map(f32::to_bits));
+            let scene = &mut self.scene;
+            let vertex = *self.welded.entry(key).or_insert_with(|| {
+                scene.vertices.push(Vertex::new(p[0], p[1], p[2], 1.));
+                scene.vertex_colors.push(color);
+                scene.vertices.len() - 1
+            });
+            vertices.push(vertex);
+        }
+        Ok(vertices)
+    }
+
+    fn normals(
+        &mut self,
+        normals: usize,
+        count: usize,
+        world: &Matrix4,
+        location: &str,
+    ) -> Result<usize, ParseError> {
+        let normals = self.attribute_data(normals, 3, Some(count), location)?;
+        let base = self.scene.normals.len();
+        for n in normals.values.chunks_exact(normals.components) {
+            let [x, y, z] = transform_normal(world, [n[0] as f32, n[1] as f32, n[2] as f32]);
+            self.scene.normals.push(Normal::new(x, y, z));
+        }
+        Ok(base)
+    }
+
+    // glTF puts the origin of images top left, OBJ bottom left
+    fn textures(
+        &mut self,
+        textures: usize,
+        count: usize,
+        location: &str,
+    ) -> Result<usize, ParseError> {
+        let textures = self.attribute_data(textures, 2, Some(count), location)?;
+        let base = self.scene.texture_coordinates.len();
+        for t in textures.values.chunks_exact(textures.components) {
+            let (u, v) = (t[0] as f32, 1. - t[1] as f32);
+            self.scene
+                .texture_coordinates
+                .push(TextureCoordinate::new(u, v, 0.));
+        }
+        Ok(base)
+    }
+
+    fn primitive(
+        &mut self,
+        primitive: &Value,
+        world: &Matrix4,
+        shared: &mut Shared,
+        location: &str,
+    ) -> Result<(), ParseError> {
+        let positions = self
+            .attribute(primitive, "POSITION", location)?
+            .ok_or_else(|| {
+                let kind = ParseErrorKind::MissingComponent("POSITION attribute".to_string());
+                self.error(kind, location)
+            })?;
+        let colors = self.attribute(primitive, "COLOR_0", location)?;
+        let vertices = match shared.vertices.get(&(positions, colors)) {
+            Some(vertices) => vertices.clone(),
+            None => {
+                let vertices = self.vertices(positions, colors, world, location)?;
+                shared
+                    .vertices
+                    .insert((positions, colors), vertices.clone());
+                vertices
+            }
+        };
+        let count = vertices.len();
+        let normal_base = match self.attribute(primitive, "NORMAL", location)? {
+            Some(normals) => Some(match shared.normals.get(&(normals, count)) {
+                Some(&normal_base) => normal_base,
+                None => {
+                    let normal_base = self.normals(normals, count, world, location)?;
+                    shared.normals.insert((normals, count), normal_base);
+                    normal_base
+                }
+            }),
+            None => None,
+        };
+        let texture_base = match self.attribute(primitive, "TEXCOORD_0", location)? {
+            Some(textures) => Some(match shared.textures.get(&(textures, count)) {
+                Some(&texture_base) => texture_base,
+                None => {
+                    let texture_base = self.textures(textures, count, location)?;
+                    shared.textures.insert((textures, count), texture_base);
+                    texture_base
+                }
+            }),
+            None => None,
+        };
+
+        let indices: Vec<usize> = match self.index(primitive, "indices", location)? {
+            Some(accessor) => {
+                let data = self.accessor(accessor)?;
+                let indices: Vec<usize> = data.values.iter().map(|&i| i as usize).collect();
+                if indices.iter().any(|&i| i >= count) {
+                    return Err(self.error(ParseErrorKind::IndexOutOfRange, location));
+                }
+                indices
+            }
+            None => (0..count).collect(),
+        };
+        let material = self.index(primitive, "material", location)?;
+        let mode = self.index(primitive, "mode", location)?.unwrap_or(4);
+        if material.is_some_and(|material| material >= self.scene.materials.len()) {
+            return Err(self.error(ParseErrorKind::IndexOutOfRange, location));
+        }
+        let corner = |i: usize| FaceVertex {
+            vertex: vertices[i],
+            texture: texture_base.map(|texture_base| texture_base + i),
+            normal: normal_base.map(|normal_base| normal_base + i),
+        };
+        // A mirroring transform turns faces inside out unless they are
+        // flipped back
+        let mirrored = determinant(world) < 0.;
+        let mut triangle = |a: usize, b: usize, c: usize| {
+            let corners = match mirrored {
+                false => [corner(a), corner(b), corner(c)],
+                true => [corner(a), corner(c), corner(b)],
+            };
+            self.scene
+                .triangles
+                .push(Triangle::new(corners, 0, material));
+        };
+        let n = indices.len();
+        match mode {
+            4 => {
+                for t in indices.chunks_exact(3) {
+                    triangle(t[0], t[1], t[2]);
+                }
+            }
+            // Every other triangle of a strip is wound the other way
+            5 => {
+                for i in 0..n.saturating_sub(2) {
+                    match i % 2 {
+                        0 => triangle(indices[i], indices[i + 1], indices[i + 2]),
+                        _ => triangle(indices[i + 1], indices[i], indices[i + 2]),
+                    }
+                }
+            }
+            6 => {
+                for i in 1..n.saturating_sub(1) {
+                    triangle(indices[0], indices[i], indices[i + 1]);
+                }
+            }
+            0 => self
+                .scene
+                .points
+                .extend(indices.iter().map(|&i| vertices[i])),
+            1 => {
+                for l in indices.chunks_exact(2) {
+                    self.scene.lines.push([vertices[l[0]], vertices[l[1]]]);
+                }
+            }
+            mode @ (2 | 3) => {
+                for l in indices.windows(2) {
+                    self.scene.lines.push([vertices[l[0]], vertices[l[1]]]);
+                }
+                if mode == 2 && n > 2 {
+                    self.scene
+                        .lines
+                        .push([vertices[indices[n - 1]], vertices[indices[0]]]);
+                }
+            }
+            _ => {
+                let kind = ParseErrorKind::Unsupported("primitive mode".to_string());
+                return Err(self.error(kind, location));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::normals::face_normal;
+
+    fn encode_base64(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let word = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |word, (i, &b)| word | (b as u32) << (16 - 8 * i));
+            for i in 0..4 {
+                match i <= chunk.len() {
+                    true => text.push(ALPHABET[(word >> (18 - 6 * i) & 63) as usize] as char),
+                    false => text.push('='),
+                }
+            }
+        }
+        text
+    }
+
+    // A unit square as a triangle strip, drawn by a root node and by a
+    // mirrored child
+    fn document() -> String {
+        let mut buffer: Vec<u8> = [0u16, 1, 2, 3]
+            .iter()
+            .flat_map(|i| i.to_le_bytes())
+            .collect();
+        let positions = [0., 0., 0., 1., 0., 0., 0., 1., 0., 1., 1., 0.];
+        buffer.extend(positions.iter().flat_map(|p: &f32| p.to_le_bytes()));
+        format!(
+            r#"{{
+  "asset": {{"version": "2.0"}},
+  "scene": 0,
+  "scenes": [{{"nodes": [0]}}],
+  "nodes": [
+    {{"name": "root", "mesh": 0, "children": [1]}},
+    {{"name": "mirror", "mesh": 0, "translation": [3, 0, 0], "scale": [-1, 1, 1]}}
+  ],
+  "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 1}}, "indices": 0, "mode": 5}}]}}],
+  "buffers": [{{"byteLength": {}, "uri": "data:application/octet-stream;base64,{}"}}],
+  "bufferViews": [
+    {{"buffer": 0, "byteLength": 8}},
+    {{"buffer": 0, "byteOffset": 8, "byteLength": 48}}
+  ],
+  "accessors": [
+    {{"bufferView": 0, "componentType": 5123, "count": 4, "type": "SCALAR"}},
+    {{"bufferView": 1, "componentType": 5126, "count": 4, "type": "VEC3"}}
+  ]
+}}"#,
+            buffer.len(),
+            encode_base64(&buffer)
+        )
+    }
+
+    fn parse(text: &str) -> Scene {
+        let path = env::temp_dir().join(format!("scop-strip-{}.gltf", std::process::id()));
+        fs::write(&path, text).unwrap();
+        let scene = GltfParser::new(path.to_str().unwrap())
+            .and_then(GltfParser::generate_scene)
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+        scene
+    }
+
+    #[test]
+    fn base64_round_trips() {
+        for bytes in [&b""[..], b"a", b"ab", b"abc", b"\xff\x00\x80\x7f"] {
+            assert_eq!(decode_base64(&encode_base64(bytes)).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn strips_and_mirrored_nodes_keep_faces_facing_out() {
+        let scene = parse(&document());
+        let nodes: Vec<(&str, Option<usize>, Option<usize>)> = scene
+            .nodes
+            .iter()
+            .map(|node| (node.name.as_str(), node.parent, node.object))
+            .collect();
+        assert_eq!(
+            nodes,
+            [("root", None, Some(0)), ("mirror", Some(0), Some(1))]
+        );
+        assert_eq!(scene.objects[1].name, "root/mirror");
+        assert_eq!(scene.triangles.len(), 4);
+        for triangle in scene.triangles.iter() {
+            let n = face_normal(&scene, triangle);
+            assert_eq!((n.x, n.y, n.z), (0., 0., 1.));
+        }
+        let mirrored: Vec<[f32; 3]> = scene.objects[1]
+            .triangles
+            .clone()
+            .flat_map(|t| scene.triangles[t].corners)
+            .map(|corner| {
+                let v = scene.vertices[corner.vertex];
+                [v.x, v.y, v.z]
+            })
+            .collect();
+        assert!(mirrored.contains(&[2., 1., 0.]));
+        assert!(mirrored.iter().all(|p| p[0] == 2. || p[0] == 3.));
+    }
+}
@@ -0,0 +1,206 @@
+use crate::matrices::Matrix;
+use crate::scene::Color;
+use crate::tuple::{vector, Vector};
+
+/// Local placement of a node relative to its parent: scaled along each axis,
+/// then rotated around `axis` by `angle` radians, then translated.
+#[derive(Clone, Copy)]
+pub struct Transform {
+    pub translation: Vector,
+    pub axis: Vector,
+    pub angle: f32,
+    pub scale: Vector,
+}
+
+/// Point light with distance attenuation, as the fragment shader expects it.
+#[derive(Clone, Copy)]
+pub struct PointLight {
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+/// What a node carries besides its transform. Models are indices into
+/// whatever list of meshes the caller keeps.
+#[derive(Clone, Copy)]
+pub enum Attachment {
+    None,
+    Model(usize),
+    Light(PointLight),
+}
+
+pub struct Node {
+    pub name: String,
+    pub attachment: Attachment,
+    transform: Transform,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    world: Matrix,
+    dirty: bool,
+}
+
+/// Tree of nodes with cached world matrices. Changing a transform marks its
+/// subtree dirty, and `update` recomputes only what changed.
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+}
+
+impl Transform {
+    pub fn from_translation(translation: Vector) -> Self {
+        Self {
+            translation,
+            axis: vector(0., 1., 0.),
+            angle: 0.,
+            scale: vector(1., 1., 1.),
+        }
+    }
+
+    pub fn matrix(&self) -> Matrix {
+        Matrix::from_scale(self.scale)
+            * Matrix::from_axis_angle(self.axis, self.angle)
+            * Matrix::from_translation(self.translation)
+    }
+}
+
+impl PointLight {
+    /// White light fading out over about 50 units.
+    pub fn new() -> Self {
+        Self {
+            ambient: Color::new(0.05, 0.05, 0.05),
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(1., 1., 1.),
+            constant: 1.,
+            linear: 0.09,
+            quadratic: 0.032,
+        }
+    }
+}
+
+impl SceneGraph {
+    pub fn new() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    /// Adds a node under `parent`, or as a root, and returns its index.
+    pub fn add(
+        &mut self,
+        name: &str,
+        parent: Option<usize>,
+        transform: Transform,
+        attachment: Attachment,
+    ) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            attachment,
+            transform,
+            parent,
+            children: Vec::new(),
+            world: Matrix::identity(),
+            dirty: true,
+        });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(id);
+        }
+        id
+    }
+
+    /// Gives access to a node's transform, so its subtree has to be updated.
+    pub fn transform_mut(&mut self, id: usize) -> &mut Transform {
+        self.mark_dirty(id);
+        &mut self.nodes[id].transform
+    }
+
+    fn mark_dirty(&mut self, id: usize) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = &mut self.nodes[id];
+            node.dirty = true;
+            stack.extend(node.children.iter().copied());
+        }
+    }
+
+    /// Recomputes the world matrices of dirty nodes. Parents are always added
+    /// before their children, so a single pass in order does it.
+    pub fn update(&mut self) {
+        for id in 0..self.nodes.len() {
+            if !self.nodes[id].dirty {
+                continue;
+            }
+            let local = self.nodes[id].transform.matrix();
+            let world = match self.nodes[id].parent {
+                Some(parent) => local * self.nodes[parent].world,
+                None => local,
+            };
+            let node = &mut self.nodes[id];
+            node.world = world;
+            node.dirty = false;
+        }
+    }
+
+    /// World matrix as of the last `update`.
+    pub fn world(&self, id: usize) -> &Matrix {
+        &self.nodes[id].world
+    }
+
+    /// Origin of a node in world space, as of the last `update`.
+    pub fn world_position(&self, id: usize) -> Vector {
+        let world = &self.nodes[id].world;
+        vector(world.data[3][0], world.data[3][1], world.data[3][2])
+    }
+
+    /// `p`, in the node's local space, moved to world space.
+    pub fn world_point(&self, id: usize, p: Vector) -> Vector {
+        let m = &self.nodes[id].world.data;
+        vector(
+            m[0][0] * p.x + m[1][0] * p.y + m[2][0] * p.z + m[3][0],
+            m[0][1] * p.x + m[1][1] * p.y + m[2][1] * p.z + m[3][1],
+            m[0][2] * p.x + m[1][2] * p.y + m[2][2] * p.z + m[3][2],
+        )
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = (usize, &Node)> {
+        self.nodes.iter().enumerate()
+    }
+
+    /// Every light with its node's index.
+    pub fn lights(&self) -> impl Iterator<Item = (usize, &PointLight)> {
+        self.nodes()
+            .filter_map(|(id, node)| match &node.attachment {
+                Attachment::Light(light) => Some((id, light)),
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_applies_per_axis_before_the_rest() {
+        let mut graph = SceneGraph::new();
+        let mut transform = Transform::from_translation(vector(1., 0., 0.));
+        transform.scale = vector(2., 3., 4.);
+        let parent = graph.add("parent", None, transform, Attachment::None);
+        let child = graph.add(
+            "child",
+            Some(parent),
+            Transform::from_translation(vector(1., 1., 1.)),
+            Attachment::None,
+        );
+        graph.update();
+        let p = graph.world_point(parent, vector(1., 1., 1.));
+        assert_eq!((p.x, p.y, p.z), (3., 3., 4.));
+        let p = graph.world_position(child);
+        assert_eq!((p.x, p.y, p.z), (3., 3., 4.));
+
+        graph.transform_mut(parent).angle = std::f32::consts::FRAC_PI_2;
+        graph.update();
+        let p = graph.world_position(child);
+        assert!((p.x - 5.).abs() < 1e-5 && (p.y - 3.).abs() < 1e-5 && (p.z + 2.).abs() < 1e-5);
+    }
+}
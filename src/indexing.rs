@@ -0,0 +1,69 @@
+use crate::scene::{FaceVertex, Scene};
+use std::collections::HashMap;
+
+/// Element indices, narrowed to 16 bits when every vertex fits.
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+/// The scene's faces, lines and points over a single vertex array, as GL
+/// wants them. Each distinct (v, vt, vn) triple of the OBJ becomes one vertex;
+/// the indices list the triangles, then the lines, then the points.
+pub struct IndexedScene {
+    pub vertices: Vec<FaceVertex>,
+    pub indices: Indices,
+    pub triangle_indices: usize,
+    pub line_indices: usize,
+    pub point_indices: usize,
+}
+
+impl Indices {
+    pub fn get(&self, i: usize) -> usize {
+        match self {
+            Indices::U16(indices) => indices[i] as usize,
+            Indices::U32(indices) => indices[i] as usize,
+        }
+    }
+}
+
+impl IndexedScene {
+    pub fn new(scene: &Scene) -> Self {
+        let position_only = |vertex: usize| FaceVertex {
+            vertex,
+            texture: None,
+            normal: None,
+        };
+        let corners = scene
+            .triangles
+            .iter()
+            .flat_map(|triangle| triangle.corners)
+            .chain(scene.lines.iter().flatten().map(|&i| position_only(i)))
+            .chain(scene.points.iter().map(|&i| position_only(i)));
+
+        let mut vertices: Vec<FaceVertex> = Vec::new();
+        let mut unique: HashMap<(usize, Option<usize>, Option<usize>), u32> = HashMap::new();
+        let mut indices: Vec<u32> = Vec::new();
+        for corner in corners {
+            let key = (corner.vertex, corner.texture, corner.normal);
+            let index = *unique.entry(key).or_insert_with(|| {
+                vertices.push(corner);
+                (vertices.len() - 1) as u32
+            });
+            indices.push(index);
+        }
+
+        let indices = if vertices.len() <= u16::MAX as usize + 1 {
+            Indices::U16(indices.into_iter().map(|i| i as u16).collect())
+        } else {
+            Indices::U32(indices)
+        };
+        Self {
+            vertices,
+            indices,
+            triangle_indices: scene.triangles.len() * 3,
+            line_indices: scene.lines.len() * 2,
+            point_indices: scene.points.len(),
+        }
+    }
+}
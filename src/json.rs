@@ -0,0 +1,356 @@
+use crate::parser::{LineContext, ParseError, ParseErrorKind};
+use std::fmt;
+
+/// JSON document, as much of it as glTF needs. Object members keep their
+/// order, and `Display` writes it back compactly.
+#[derive(Clone)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// Non-negative integers only, as used for indices and counts.
+    pub fn as_usize(&self) -> Option<usize> {
+        let number = self.as_f64()?;
+        (number >= 0. && number.fract() == 0. && number <= usize::MAX as f64)
+            .then_some(number as usize)
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Items of the array at `key`, none when it is missing.
+    pub fn items(&self, key: &str) -> &[Value] {
+        self.get(key).and_then(Value::as_array).unwrap_or(&[])
+    }
+}
+
+/// An object from its members, e.g. `object(vec![("count", 3.into())])`.
+pub fn object(members: Vec<(&str, Value)>) -> Value {
+    Value::Object(
+        members
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+    )
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::Number(value as f64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Self {
+        Value::Array(items)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, string: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in string.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            // JSON has no infinities nor NaN
+            Value::Number(number) if !number.is_finite() => write!(f, "null"),
+            // Numbers that came from an f32 are written as short as the f32
+            Value::Number(number) if *number as f32 as f64 == *number => {
+                write!(f, "{}", *number as f32)
+            }
+            Value::Number(number) => write!(f, "{}", number),
+            Value::String(string) => write_string(f, string),
+            Value::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+// Nesting deeper than this is refused rather than overflowing the stack
+const MAX_DEPTH: usize = 128;
+
+struct JsonParser<'a> {
+    path: &'a str,
+    text: &'a str,
+    offset: usize,
+}
+
+/// Parses `text`, read from `path`, reporting errors at their line and
+/// column.
+pub fn parse(text: &str, path: &str) -> Result<Value, ParseError> {
+    let mut parser = JsonParser {
+        path,
+        text,
+        offset: 0,
+    };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    if parser.offset < text.len() {
+        return Err(parser.error("end of document"));
+    }
+    Ok(value)
+}
+
+impl<'a> JsonParser<'a> {
+    // Points at the character where `what` was expected
+    fn error(&self, what: &str) -> ParseError {
+        let start = self.text[..self.offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = self.text[self.offset..]
+            .find('\n')
+            .map_or(self.text.len(), |i| self.offset + i);
+        let line = &self.text[start..end];
+        let ctx = LineContext {
+            path: self.path,
+            number: self.text[..start].matches('\n').count() + 1,
+            line,
+        };
+        let token_end = line[self.offset - start..]
+            .char_indices()
+            .nth(1)
+            .map_or(line.len(), |(i, _)| self.offset - start + i);
+        let kind = ParseErrorKind::Expected(what.to_string());
+        ctx.error(kind, &line[self.offset - start..token_end])
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.offset..];
+        self.offset += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.offset).copied()
+    }
+
+    fn eat(&mut self, expected: &str) -> bool {
+        if self.text[self.offset..].starts_with(expected) {
+            self.offset += expected.len();
+            return true;
+        }
+        false
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, ParseError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("shallower nesting"));
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'{') => self.object(depth),
+            Some(b'[') => self.array(depth),
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ if self.eat("true") => Ok(Value::Bool(true)),
+            _ if self.eat("false") => Ok(Value::Bool(false)),
+            _ if self.eat("null") => Ok(Value::Null),
+            _ => Err(self.error("value")),
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Value, ParseError> {
+        self.offset += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.eat("}") {
+            return Ok(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("member name"));
+            }
+            let name = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(":") {
+                return Err(self.error("':'"));
+            }
+            members.push((name, self.value(depth + 1)?));
+            self.skip_whitespace();
+            if self.eat("}") {
+                return Ok(Value::Object(members));
+            }
+            if !self.eat(",") {
+                return Err(self.error("',' or '}'"));
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Value, ParseError> {
+        self.offset += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.eat("]") {
+            return Ok(Value::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            if self.eat("]") {
+                return Ok(Value::Array(items));
+            }
+            if !self.eat(",") {
+                return Err(self.error("',' or ']'"));
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.offset;
+        let rest = &self.text[start..];
+        let len = rest
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(rest.len());
+        match rest[..len].parse::<f64>() {
+            Ok(number) => {
+                self.offset += len;
+                Ok(Value::Number(number))
+            }
+            Err(_) => Err(self.error("number")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.offset += 1;
+        let mut string = String::new();
+        loop {
+            let rest = &self.text[self.offset..];
+            let len = rest.find(['"', '\\']).ok_or_else(|| self.error("'\"'"))?;
+            string.push_str(&rest[..len]);
+            self.offset += len;
+            if self.eat("\"") {
+                return Ok(string);
+            }
+            self.offset += 1;
+            let escaped = match self.peek() {
+                Some(b'"') => '"',
+                Some(b'\\') => '\\',
+                Some(b'/') => '/',
+                Some(b'b') => '\u{8}',
+                Some(b'f') => '\u{c}',
+                Some(b'n') => '\n',
+                Some(b'r') => '\r',
+                Some(b't') => '\t',
+                Some(b'u') => {
+                    self.offset += 1;
+                    string.push(self.unicode_escape()?);
+                    continue;
+                }
+                _ => return Err(self.error("escape sequence")),
+            };
+            self.offset += 1;
+            string.push(escaped);
+        }
+    }
+
+    // The XXXX of \uXXXX, with the low half of a surrogate pair if needed
+    fn unicode_escape(&mut self) -> Result<char, ParseError> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| self.error("unicode scalar"));
+        }
+        if !self.eat("\\u") {
+            return Err(self.error("low surrogate"));
+        }
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err(self.error("low surrogate"));
+        }
+        let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+        char::from_u32(code).ok_or_else(|| self.error("unicode scalar"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, ParseError> {
+        let digits = self
+            .text
+            .get(self.offset..self.offset + 4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("4 hex digits"))?;
+        self.offset += 4;
+        Ok(u32::from_str_radix(digits, 16).unwrap())
+    }
+}
@@ -1,28 +1,60 @@
 extern crate glfw;
 extern crate image;
 
+mod cache;
 mod camera;
+mod glb;
+mod gltf;
+mod graph;
+mod indexing;
+mod json;
 mod matrices;
+mod mesh;
+mod model;
+mod mtl;
+mod normals;
 mod parser;
+mod ply;
 mod scene;
 mod shader;
+mod simplify;
+mod stl;
+mod tangents;
+mod texture;
 mod tuple;
+mod uvs;
+mod validation;
+mod writer;
 
 use c_str_macro::c_str;
+use cache::Cache;
 use camera::Camera;
-use gl::types::{GLfloat, GLsizei, GLsizeiptr};
 use glfw::{Action, Context, Glfw, GlfwReceiver, Key, PWindow, WindowEvent};
-use matrices::{perspective, Matrix};
-use parser::Parser;
-use scene::Scene;
+use gltf::GltfParser;
+use graph::{Attachment, PointLight, SceneGraph, Transform};
+use matrices::perspective;
+use mesh::{Mesh, MeshData};
+use model::{Model, ViewOptions};
+use normals::{NormalOptions, Shading, Weighting};
+use parser::{ParseError, Parser};
+use ply::PlyParser;
+use scene::{FaceVertex, Scene, Triangle, Vertex};
 use shader::Shader;
-use std::mem;
+use std::env;
+use std::ffi::CString;
 use std::path::Path;
-use std::{ffi::c_void, ptr};
-use tuple::{normalize, vector};
+use std::process;
+use std::thread;
+use stl::StlParser;
+use tuple::{magnitude, vector};
+use uvs::Projection;
+use validation::ValidationReport;
+use writer::WriteOptions;
 
 const WINDOW_WIDTH: u32 = 1920;
 const WINDOW_HEIGHT: u32 = 1080;
+// Size of the pointLights array in fragment.shader
+const MAX_POINT_LIGHTS: usize = 4;
 
 fn create_configured_window(glfw: &mut Glfw) -> (PWindow, GlfwReceiver<(f64, WindowEvent)>) {
     glfw.window_hint(glfw::WindowHint::ContextVersionMajor(3));
@@ -54,51 +86,231 @@ fn create_configured_window(glfw: &mut Glfw) -> (PWindow, GlfwReceiver<(f64, Win
     (window, events)
 }
 
+const DEFAULT_MODEL: &str = "./resources/42.obj";
+
+// Spin speed of the first model in radians per second, the next ones go a bit
+// faster each
+const SPIN_SPEED: f32 = 0.5;
+
+fn extension(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+// Picks the parser from the file extension, OBJ being the default
+fn parse_file(path: &str) -> Result<Scene, ParseError> {
+    match extension(path).as_deref() {
+        Some("stl") => StlParser::new(path)?.generate_scene(),
+        Some("ply") => PlyParser::new(path)?.generate_scene(),
+        Some("gltf" | "glb") => GltfParser::new(path)?.generate_scene(),
+        _ => {
+            let threads = thread::available_parallelism().map_or(1, |n| n.get());
+            Parser::new(path)?.generate_scene_parallel(threads)
+        }
+    }
+}
+
+// glTF files are already binary, and their buffers and images are not
+// tracked by the cache, so they are always parsed
+fn cacheable(path: &str) -> bool {
+    !matches!(extension(path).as_deref(), Some("gltf" | "glb"))
+}
+
+// Reads the binary cache of `path` when it is up to date, along with the
+// meshes it holds, and parses the file otherwise
+fn load_scene(path: &str) -> (Scene, Option<Cache>) {
+    let cached = cacheable(path).then(|| cache::load(Path::new(path)));
+    if let Some((scene, cache)) = cached.flatten() {
+        println!("Loaded {} from its cache", path);
+        return (scene, Some(cache));
+    }
+    match parse_file(path) {
+        Ok(scene) => (scene, None),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// Uploads the levels of detail of the cache when there is one, and otherwise
+// simplifies the scene into them and refreshes the cache, which holds their
+// meshes as the default view options make them. With `verbose`, reports how well the full detail mesh shares its
+// vertices.
+fn load_model(
+    path: &str,
+    scene: &Scene,
+    cache: Option<Cache>,
+    options: &ViewOptions,
+    verbose: bool,
+) -> Model {
+    let report = |meshes: &[MeshData]| {
+        if let Some(mesh) = meshes.first().filter(|_| verbose) {
+            println!(
+                "Indexed -> {} triangles, {} vertices, reuse ratio {:.2}",
+                mesh.triangle_indices / 3,
+                mesh.vertex_count(),
+                mesh.reuse_ratio()
+            );
+        }
+    };
+    if let Some(mut cache) = cache {
+        let mut lods = vec![scene.clone()];
+        lods.append(&mut cache.lods);
+        let meshes = cache.meshes();
+        report(&meshes);
+        return Model::new(scene, lods, &meshes);
+    }
+    let (lods, meshes) = model::prepare(scene, options);
+    report(&meshes);
+    if cacheable(path) {
+        if let Err(e) = cache::save(&lods, &meshes, Path::new(path)) {
+            eprintln!("Could not write the cache of {}: {}", path, e);
+        }
+    }
+    Model::new(scene, lods, &meshes)
+}
+
+// Element counts of the scene, and with `verbose` every vertex, triangle,
+// object and group, which floods the terminal on large models
+fn print_scene(scene: &Scene, verbose: bool) {
+    let groups: usize = scene.objects.iter().map(|object| object.groups.len()).sum();
+    println!(
+        "Vertices -> {}, triangles -> {}, lines -> {}, points -> {}, objects -> {}, groups -> {}",
+        scene.vertices.len(),
+        scene.triangles.len(),
+        scene.lines.len(),
+        scene.points.len(),
+        scene.objects.len(),
+        groups
+    );
+    println!(
+        "Texture coordinates -> {}, normals -> {}, vertex colors -> {}",
+        scene.texture_coordinates.len(),
+        scene.normals.len(),
+        scene.vertex_colors.len()
+    );
+    for property in scene.vertex_properties.iter() {
+        println!("Vertex property -> {}", property.name);
+    }
+    if verbose {
+        for v in scene.vertices.iter() {
+            println!("Vertex -> x: {}, y: {}, z: {}, w: {}", v.x, v.y, v.z, v.w);
+        }
+        for triangle in scene.triangles.iter() {
+            let [a, b, c] = triangle.corners;
+            println!("Triangle -> {} {} {}", a.vertex, b.vertex, c.vertex);
+        }
+        for object in scene.objects.iter() {
+            println!(
+                "Object -> {}: triangles {:?}, {} groups",
+                object.name,
+                object.triangles,
+                object.groups.len()
+            );
+            for group in object.groups.iter() {
+                println!(
+                    "    Group -> {}: triangles {:?}",
+                    group.names.join(" "),
+                    group.triangles
+                );
+            }
+        }
+    }
+    if let Some(bounds) = scene.bounding_box() {
+        let centroid = scene.centroid().unwrap();
+        println!(
+            "Bounding box -> min ({}, {}, {}), max ({}, {}, {}), centroid ({}, {}, {})",
+            bounds.min.x,
+            bounds.min.y,
+            bounds.min.z,
+            bounds.max.x,
+            bounds.max.y,
+            bounds.max.z,
+            centroid.x,
+            centroid.y,
+            centroid.z
+        );
+    }
+}
+
 fn main() {
+    // scop [--validate] [--verbose] [--write out.obj|out.glb] [model.obj|model.stl|model.ply|model.gltf|model.glb...]
+    let mut validate = false;
+    let mut verbose = false;
+    let mut output: Option<String> = None;
+    let mut paths: Vec<String> = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--validate" => validate = true,
+            "--verbose" => verbose = true,
+            "--write" => match args.next() {
+                Some(path) => output = Some(path),
+                None => {
+                    eprintln!("--write needs an output file");
+                    process::exit(1);
+                }
+            },
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(DEFAULT_MODEL.to_string());
+    }
+    // An OBJ holds a single model, only a .glb keeps several apart
+    if let Some(output) = &output {
+        if paths.len() > 1 && extension(output).as_deref() != Some("glb") {
+            eprintln!("{}: only a .glb can hold several models", output);
+            process::exit(1);
+        }
+    }
+    let (scenes, caches): (Vec<Scene>, Vec<Option<Cache>>) =
+        paths.iter().map(|path| load_scene(path)).unzip();
+    if validate {
+        let mut clean = true;
+        for (path, scene) in paths.iter().zip(scenes.iter()) {
+            let report = ValidationReport::new(scene);
+            println!("{}\n{}", path, report);
+            clean &= report.is_clean();
+        }
+        process::exit(if clean { 0 } else { 1 });
+    }
+    // Saves the models with the normals they were missing
+    if let Some(output) = output {
+        let scenes: Vec<Scene> = scenes
+            .iter()
+            .map(|scene| {
+                let mut scene = scene.clone();
+                normals::generate_normals(&mut scene, NormalOptions::default());
+                scene
+            })
+            .collect();
+        let result = match extension(&output).as_deref() {
+            Some("glb") => {
+                let labels = paths.iter().map(|path| {
+                    Path::new(path)
+                        .file_name()
+                        .map_or(path.as_str(), |name| name.to_str().unwrap_or(path))
+                });
+                let scenes: Vec<(&str, &Scene)> = labels.zip(scenes.iter()).collect();
+                glb::save(&scenes, Path::new(&output))
+            }
+            _ => writer::save(&scenes[0], Path::new(&output), &WriteOptions::default()),
+        };
+        if let Err(e) = result {
+            eprintln!("{}: {}", output, e);
+            process::exit(1);
+        }
+        process::exit(0);
+    }
+
     // Init OpenGL
     let mut glfw = glfw::init(glfw::fail_on_errors).unwrap();
 
     // Create a window
     let (mut window, events) = create_configured_window(&mut glfw);
-    let vertices: [f32; 288] = [
-        // positions          // normals           // texture coords
-        -0.5, -0.5, -0.5, 0.0, 0.0, -1.0, 0.0, 0.0, 0.5, -0.5, -0.5, 0.0, 0.0, -1.0, 1.0, 0.0, 0.5,
-        0.5, -0.5, 0.0, 0.0, -1.0, 1.0, 1.0, 0.5, 0.5, -0.5, 0.0, 0.0, -1.0, 1.0, 1.0, -0.5, 0.5,
-        -0.5, 0.0, 0.0, -1.0, 0.0, 1.0, -0.5, -0.5, -0.5, 0.0, 0.0, -1.0, 0.0, 0.0, -0.5, -0.5,
-        0.5, 0.0, 0.0, 1.0, 0.0, 0.0, 0.5, -0.5, 0.5, 0.0, 0.0, 1.0, 1.0, 0.0, 0.5, 0.5, 0.5, 0.0,
-        0.0, 1.0, 1.0, 1.0, 0.5, 0.5, 0.5, 0.0, 0.0, 1.0, 1.0, 1.0, -0.5, 0.5, 0.5, 0.0, 0.0, 1.0,
-        0.0, 1.0, -0.5, -0.5, 0.5, 0.0, 0.0, 1.0, 0.0, 0.0, -0.5, 0.5, 0.5, -1.0, 0.0, 0.0, 1.0,
-        0.0, -0.5, 0.5, -0.5, -1.0, 0.0, 0.0, 1.0, 1.0, -0.5, -0.5, -0.5, -1.0, 0.0, 0.0, 0.0, 1.0,
-        -0.5, -0.5, -0.5, -1.0, 0.0, 0.0, 0.0, 1.0, -0.5, -0.5, 0.5, -1.0, 0.0, 0.0, 0.0, 0.0,
-        -0.5, 0.5, 0.5, -1.0, 0.0, 0.0, 1.0, 0.0, 0.5, 0.5, 0.5, 1.0, 0.0, 0.0, 1.0, 0.0, 0.5, 0.5,
-        -0.5, 1.0, 0.0, 0.0, 1.0, 1.0, 0.5, -0.5, -0.5, 1.0, 0.0, 0.0, 0.0, 1.0, 0.5, -0.5, -0.5,
-        1.0, 0.0, 0.0, 0.0, 1.0, 0.5, -0.5, 0.5, 1.0, 0.0, 0.0, 0.0, 0.0, 0.5, 0.5, 0.5, 1.0, 0.0,
-        0.0, 1.0, 0.0, -0.5, -0.5, -0.5, 0.0, -1.0, 0.0, 0.0, 1.0, 0.5, -0.5, -0.5, 0.0, -1.0, 0.0,
-        1.0, 1.0, 0.5, -0.5, 0.5, 0.0, -1.0, 0.0, 1.0, 0.0, 0.5, -0.5, 0.5, 0.0, -1.0, 0.0, 1.0,
-        0.0, -0.5, -0.5, 0.5, 0.0, -1.0, 0.0, 0.0, 0.0, -0.5, -0.5, -0.5, 0.0, -1.0, 0.0, 0.0, 1.0,
-        -0.5, 0.5, -0.5, 0.0, 1.0, 0.0, 0.0, 1.0, 0.5, 0.5, -0.5, 0.0, 1.0, 0.0, 1.0, 1.0, 0.5,
-        0.5, 0.5, 0.0, 1.0, 0.0, 1.0, 0.0, 0.5, 0.5, 0.5, 0.0, 1.0, 0.0, 1.0, 0.0, -0.5, 0.5, 0.5,
-        0.0, 1.0, 0.0, 0.0, 0.0, -0.5, 0.5, -0.5, 0.0, 1.0, 0.0, 0.0, 1.0,
-    ];
-
-    let cube_positions = [
-        vector(0.0, 0.0, 0.0),
-        vector(2.0, 5.0, -15.0),
-        vector(-1.5, -2.2, -2.5),
-        vector(-3.8, -2.0, -12.3),
-        vector(2.4, -0.4, -3.5),
-        vector(-1.7, 3.0, -7.5),
-        vector(1.3, -2.0, -2.5),
-        vector(1.5, 2.0, -2.5),
-        vector(1.5, 0.2, -1.5),
-        vector(-1.3, 1.0, -1.5),
-    ];
-    let point_light_positions = [
-        vector(0.7, 0.2, 2.0),
-        vector(2.3, -3.3, -4.0),
-        vector(-4.0, 2.0, -12.0),
-        vector(0.0, 0.0, -3.0),
-    ];
 
     // We need to write manually at least 2 shaders: vertex shader and fragment shader
     let mut shader = Shader::new(
@@ -110,136 +322,84 @@ fn main() {
         "./src/shaders/light_fragment.shader",
     );
     let mut cam = Camera::new(WINDOW_WIDTH, WINDOW_HEIGHT);
-    let parser = Parser::new("./resources/42.obj").unwrap();
-    let scene: Scene = parser.generate_scene().unwrap();
-    for i in 0..scene.vertices.len() {
-        println!(
-            "Vertex -> x: {}, y: {}, z: {}, w: {}",
-            scene.vertices[i].x, scene.vertices[i].y, scene.vertices[i].z, scene.vertices[i].w
-        );
+    let mut options = ViewOptions {
+        normals: NormalOptions::default(),
+        uvs: None,
+        checker: false,
+    };
+    let mut models: Vec<Model> = Vec::new();
+    for ((path, scene), cache) in paths.iter().zip(scenes.iter()).zip(caches) {
+        print_scene(scene, verbose);
+        models.push(load_model(path, scene, cache, &options, verbose));
     }
-    let (vbo, vao, light_vao, diffuse_map, specular_map) = unsafe {
-        gl::Enable(gl::DEPTH_TEST);
-
-        // Load vertex data
-        let (mut vbo, mut vao) = (0, 0);
-        gl::GenVertexArrays(1, &mut vao);
-        gl::GenBuffers(1, &mut vbo);
-
-        gl::BindVertexArray(vao);
 
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            (vertices.len() * std::mem::size_of::<GLfloat>()) as GLsizeiptr,
-            &vertices[0] as *const f32 as *const std::os::raw::c_void,
-            gl::STATIC_DRAW,
-        );
-
-        let stride = (8 * mem::size_of::<GLfloat>()) as GLsizei;
-        // position attrib
-        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, ptr::null());
-        gl::EnableVertexAttribArray(0);
-        // normal attrib
-        gl::VertexAttribPointer(
-            1,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            stride,
-            (3 * mem::size_of::<GLfloat>()) as *const c_void,
-        );
-        gl::EnableVertexAttribArray(1);
-        // texture coord attrib
-        gl::VertexAttribPointer(
-            2,
-            2,
-            gl::FLOAT,
-            gl::FALSE,
-            stride,
-            (6 * mem::size_of::<GLfloat>()) as *const c_void,
+    // Models side by side along X, each spinning around the center of its
+    // bounding box, wherever its file placed it
+    let mut graph = SceneGraph::new();
+    let mut spinning: Vec<usize> = Vec::new();
+    let mut offset = 0.;
+    for (i, (path, model)) in paths.iter().zip(models.iter()).enumerate() {
+        if i > 0 {
+            offset += model.diagonal / 2.;
+        }
+        let placement = graph.add(
+            path,
+            None,
+            Transform::from_translation(vector(offset, 0., 0.)),
+            Attachment::None,
         );
-        gl::EnableVertexAttribArray(2);
-
-        // Load light data
-        let mut light_vao = 0;
-        gl::GenVertexArrays(1, &mut light_vao);
-        gl::BindVertexArray(light_vao);
-        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, ptr::null());
-        gl::EnableVertexAttribArray(0);
-
-        let mut diffuse_map = 0;
-
-        // Generate diffuse_map texture
-        gl::GenTextures(1, &mut diffuse_map);
-        gl::BindTexture(gl::TEXTURE_2D, diffuse_map);
-
-        // Texture wrapping
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-
-        // texture filtering
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-
-        // load diffuse_map texture
-        let img =
-            image::open(&Path::new("./resources/container2.png")).expect("Failed to load texture");
-        let data = img.as_bytes();
-        gl::TexImage2D(
-            gl::TEXTURE_2D,
-            0,
-            gl::RGB as i32,
-            img.width() as i32,
-            img.height() as i32,
-            0,
-            gl::RGBA,
-            gl::UNSIGNED_BYTE,
-            &data[0] as *const u8 as *const c_void,
+        graph.add(
+            &format!("{}/geometry", path),
+            Some(placement),
+            Transform::from_translation(-model.center),
+            Attachment::Model(i),
         );
-        gl::GenerateMipmap(gl::TEXTURE_2D);
-
-        let mut specular_map = 0;
-
-        // Generate diffuse_map texture
-        gl::GenTextures(1, &mut specular_map);
-        gl::BindTexture(gl::TEXTURE_2D, specular_map);
-
-        // Texture wrapping
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-
-        // texture filtering
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-
-        // load diffuse_map texture
-        let img = image::open(&Path::new("./resources/container2_specular.png"))
-            .expect("Failed to load texture");
-        let data = img.as_bytes();
-        gl::TexImage2D(
-            gl::TEXTURE_2D,
-            0,
-            gl::RGB as i32,
-            img.width() as i32,
-            img.height() as i32,
-            0,
-            gl::RGBA,
-            gl::UNSIGNED_BYTE,
-            &data[0] as *const u8 as *const c_void,
+        spinning.push(placement);
+        offset += model.diagonal / 2.;
+    }
+    let point_light_positions = [
+        vector(0.7, 0.2, 2.0),
+        vector(2.3, -3.3, -4.0),
+        vector(-4.0, 2.0, -12.0),
+        vector(0.0, 0.0, -3.0),
+    ];
+    for (i, position) in point_light_positions.into_iter().enumerate() {
+        let mut transform = Transform::from_translation(position);
+        transform.scale = vector(0.2, 0.2, 0.2);
+        graph.add(
+            &format!("light {}", i),
+            None,
+            transform,
+            Attachment::Light(PointLight::new()),
         );
-        gl::GenerateMipmap(gl::TEXTURE_2D);
-
-        shader.use_program();
-        shader.set_int(c_str!("material.diffuse"), 0);
-        shader.set_int(c_str!("material.specular"), 1);
+    }
+    graph.update();
+    println!("Scene graph -> {} nodes", graph.nodes().count());
+    if verbose {
+        for (id, node) in graph.nodes() {
+            let p = graph.world_position(id);
+            println!("Node {} -> x: {}, y: {}, z: {}", node.name, p.x, p.y, p.z);
+        }
+    }
 
-        (vbo, vao, light_vao, diffuse_map, specular_map)
-    };
+    let light_mesh = Mesh::new(&unit_cube());
+    let checker_map = texture::checker_texture();
+    unsafe {
+        gl::Enable(gl::DEPTH_TEST);
+        gl::PointSize(4.);
+    }
+    shader.use_program();
+    shader.set_int(c_str!("material.diffuse"), 0);
+    shader.set_int(c_str!("material.specular"), 1);
+    shader.set_int(c_str!("material.normal"), 2);
 
     while !window.should_close() {
         cam.update_delta_time(glfw.get_time() as f32);
+        for (i, &node) in spinning.iter().enumerate() {
+            let speed = SPIN_SPEED * (1. + i as f32 * 0.25);
+            graph.transform_mut(node).angle = glfw.get_time() as f32 * speed;
+        }
+        graph.update();
         unsafe {
             gl::ClearColor(0.1 as f32, 0.1 as f32, 0.1 as f32, 1.0 as f32);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
@@ -247,7 +407,6 @@ fn main() {
             // object shader
             shader.use_program();
             shader.set_tuple(c_str!("viewPos"), cam.camera_position);
-            shader.set_float(c_str!("material.shininess"), 32.);
 
             // directional light
             shader.set_vector(c_str!("dirLight.direction"), -0.2, -1.0, -0.3);
@@ -255,38 +414,21 @@ fn main() {
             shader.set_vector(c_str!("dirLight.diffuse"), 0.4, 0.4, 0.4);
             shader.set_vector(c_str!("dirLight.specular"), 0.5, 0.5, 0.5);
 
-            // point light 1
-            shader.set_tuple(c_str!("pointLights[0].position"), point_light_positions[0]);
-            shader.set_vector(c_str!("pointLights[0].ambient"), 0.05, 0.05, 0.05);
-            shader.set_vector(c_str!("pointLights[0].diffuse"), 0.8, 0.8, 0.8);
-            shader.set_vector(c_str!("pointLights[0].specular"), 1.0, 1.0, 1.0);
-            shader.set_float(c_str!("pointLights[0].constant"), 1.0);
-            shader.set_float(c_str!("pointLights[0].linear"), 0.09);
-            shader.set_float(c_str!("pointLights[0].quadratic"), 0.032);
-            // point light 2
-            shader.set_tuple(c_str!("pointLights[1].position"), point_light_positions[1]);
-            shader.set_vector(c_str!("pointLights[1].ambient"), 0.05, 0.05, 0.05);
-            shader.set_vector(c_str!("pointLights[1].diffuse"), 0.8, 0.8, 0.8);
-            shader.set_vector(c_str!("pointLights[1].specular"), 1.0, 1.0, 1.0);
-            shader.set_float(c_str!("pointLights[1].constant"), 1.0);
-            shader.set_float(c_str!("pointLights[1].linear"), 0.09);
-            shader.set_float(c_str!("pointLights[1].quadratic"), 0.032);
-            // point light 3
-            shader.set_tuple(c_str!("pointLights[2].position"), point_light_positions[2]);
-            shader.set_vector(c_str!("pointLights[2].ambient"), 0.05, 0.05, 0.05);
-            shader.set_vector(c_str!("pointLights[2].diffuse"), 0.8, 0.8, 0.8);
-            shader.set_vector(c_str!("pointLights[2].specular"), 1.0, 1.0, 1.0);
-            shader.set_float(c_str!("pointLights[2].constant"), 1.0);
-            shader.set_float(c_str!("pointLights[2].linear"), 0.09);
-            shader.set_float(c_str!("pointLights[2].quadratic"), 0.032);
-            // point light 4
-            shader.set_tuple(c_str!("pointLights[3].position"), point_light_positions[3]);
-            shader.set_vector(c_str!("pointLights[3].ambient"), 0.05, 0.05, 0.05);
-            shader.set_vector(c_str!("pointLights[3].diffuse"), 0.8, 0.8, 0.8);
-            shader.set_vector(c_str!("pointLights[3].specular"), 1.0, 1.0, 1.0);
-            shader.set_float(c_str!("pointLights[3].constant"), 1.0);
-            shader.set_float(c_str!("pointLights[3].linear"), 0.09);
-            shader.set_float(c_str!("pointLights[3].quadratic"), 0.032);
+            // point lights
+            let mut light_count = 0;
+            for (i, (id, light)) in graph.lights().take(MAX_POINT_LIGHTS).enumerate() {
+                let uniform =
+                    |field: &str| CString::new(format!("pointLights[{}].{}", i, field)).unwrap();
+                shader.set_tuple(&uniform("position"), graph.world_position(id));
+                shader.set_color(&uniform("ambient"), light.ambient);
+                shader.set_color(&uniform("diffuse"), light.diffuse);
+                shader.set_color(&uniform("specular"), light.specular);
+                shader.set_float(&uniform("constant"), light.constant);
+                shader.set_float(&uniform("linear"), light.linear);
+                shader.set_float(&uniform("quadratic"), light.quadratic);
+                light_count += 1;
+            }
+            shader.set_int(c_str!("pointLightCount"), light_count);
 
             // camera transformation
             shader.set_matrix(c_str!("view"), &cam.look_at());
@@ -302,22 +444,16 @@ fn main() {
                 ),
             );
 
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, diffuse_map);
-
-            gl::ActiveTexture(gl::TEXTURE1);
-            gl::BindTexture(gl::TEXTURE_2D, specular_map);
-
             // model transformations
-            gl::BindVertexArray(vao);
-            for (i, cube_position) in cube_positions.iter().enumerate() {
-                let model = Matrix::from_translation(*cube_position);
-                let angle: f32 = 20. * i as f32;
-                shader.set_matrix(
-                    c_str!("model"),
-                    &(Matrix::from_axis_angle(normalize(vector(1.0, 0.3, 0.5)), angle) * model),
-                );
-                gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            let checker = options.checker.then_some(checker_map);
+            for (id, node) in graph.nodes() {
+                if let Attachment::Model(m) = node.attachment {
+                    let model = &models[m];
+                    shader.set_matrix(c_str!("model"), graph.world(id));
+                    let distance =
+                        magnitude(cam.camera_position - graph.world_point(id, model.center));
+                    model.draw(&shader, distance, checker);
+                }
             }
             // light shader
             light_shader.use_program();
@@ -337,32 +473,91 @@ fn main() {
             );
 
             // light model
-            gl::BindVertexArray(light_vao);
-            for (_, point_light) in point_light_positions.iter().enumerate() {
-                let model = Matrix::from_translation(*point_light);
-                let model = Matrix::from_scale(0.2) * model;
-                light_shader.set_matrix(c_str!("model"), &model);
-                gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            for (id, _) in graph.lights() {
+                light_shader.set_matrix(c_str!("model"), graph.world(id));
+                light_mesh.draw();
+            }
+
+            // line and point elements
+            for (id, node) in graph.nodes() {
+                if let Attachment::Model(m) = node.attachment {
+                    light_shader.set_matrix(c_str!("model"), graph.world(id));
+                    models[m].draw_lines_and_points();
+                }
             }
 
             handle_keyboard_input(&mut window, &mut cam);
         }
-        handle_window_events(&mut window, &events, &mut cam);
+        if handle_window_events(&mut window, &events, &mut cam, &mut options) {
+            for model in models.iter_mut() {
+                model.rebuild(&options);
+            }
+        }
         window.swap_buffers();
         glfw.poll_events();
     }
+}
 
-    unsafe {
-        gl::DeleteVertexArrays(1, &vao);
-        gl::DeleteBuffers(1, &vbo);
+// Unit cube centered on the origin, marking the point lights
+fn unit_cube() -> Scene {
+    let mut scene = Scene::new();
+    for i in 0..8 {
+        let coordinate = |bit: usize| if i & bit == 0 { -0.5 } else { 0.5 };
+        scene
+            .vertices
+            .push(Vertex::new(coordinate(1), coordinate(2), coordinate(4), 1.));
     }
+    let faces: [[usize; 4]; 6] = [
+        [0, 2, 3, 1],
+        [4, 5, 7, 6],
+        [0, 4, 6, 2],
+        [1, 3, 7, 5],
+        [0, 1, 5, 4],
+        [2, 6, 7, 3],
+    ];
+    let corner = |vertex: usize| FaceVertex {
+        vertex,
+        texture: None,
+        normal: None,
+    };
+    for [a, b, c, d] in faces {
+        scene
+            .triangles
+            .push(Triangle::new([corner(a), corner(b), corner(c)], 0, None));
+        scene
+            .triangles
+            .push(Triangle::new([corner(a), corner(c), corner(d)], 0, None));
+    }
+    scene
 }
 
+fn next_projection(projection: Option<Projection>) -> Option<Projection> {
+    match projection {
+        None => Some(Projection::Planar),
+        Some(Projection::Planar) => Some(Projection::Spherical),
+        Some(Projection::Spherical) => Some(Projection::Cylindrical),
+        Some(Projection::Cylindrical) => Some(Projection::Box),
+        Some(Projection::Box) => None,
+    }
+}
+
+fn next_shading(shading: Shading) -> Shading {
+    match shading {
+        Shading::Groups(_) => Shading::Flat,
+        Shading::Flat => Shading::Smooth(Weighting::Area),
+        Shading::Smooth(Weighting::Area) => Shading::Smooth(Weighting::Angle),
+        Shading::Smooth(Weighting::Angle) => Shading::Groups(Weighting::Area),
+    }
+}
+
+// Returns whether the model has to be rebuilt
 fn handle_window_events(
     window: &mut glfw::Window,
     events: &GlfwReceiver<(f64, WindowEvent)>,
     cam: &mut Camera,
-) {
+    options: &mut ViewOptions,
+) -> bool {
+    let mut rebuild = false;
     for (_, event) in glfw::flush_messages(events) {
         match event {
             glfw::WindowEvent::FramebufferSize(width, height) => unsafe {
@@ -377,9 +572,31 @@ fn handle_window_events(
             glfw::WindowEvent::Scroll(_, yoffset) => {
                 cam.handle_scroll(yoffset as f32);
             }
+            glfw::WindowEvent::Key(Key::N, _, Action::Press, _) => {
+                // Cycle through the normal generation modes
+                options.normals.shading = next_shading(options.normals.shading);
+                rebuild = true;
+            }
+            glfw::WindowEvent::Key(Key::C, _, Action::Press, _) => {
+                // Toggle hard edges between faces more than 30 degrees apart
+                options.normals.crease_angle = match options.normals.crease_angle {
+                    Some(_) => None,
+                    None => Some(30.),
+                };
+                rebuild = true;
+            }
+            glfw::WindowEvent::Key(Key::U, _, Action::Press, _) => {
+                // Cycle through the texture coordinate projections
+                options.uvs = next_projection(options.uvs);
+                rebuild = true;
+            }
+            glfw::WindowEvent::Key(Key::K, _, Action::Press, _) => {
+                options.checker = !options.checker;
+            }
             _ => {}
         }
     }
+    rebuild
 }
 
 fn handle_keyboard_input(window: &mut glfw::Window, cam: &mut Camera) {
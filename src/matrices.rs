@@ -49,11 +49,11 @@ impl Matrix {
         m
     }
 
-    pub fn from_scale(scale: f32) -> Self {
+    pub fn from_scale(scale: Vector) -> Self {
         let mut m = Matrix::identity();
-        m.data[0][0] = scale;
-        m.data[1][1] = scale;
-        m.data[2][2] = scale;
+        m.data[0][0] = scale.x;
+        m.data[1][1] = scale.y;
+        m.data[2][2] = scale.z;
         m
     }
 
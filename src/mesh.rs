@@ -0,0 +1,280 @@
+use crate::indexing::{IndexedScene, Indices};
+use crate::scene::{FaceVertex, Scene};
+use crate::tangents::generate_tangents;
+use gl::types::{GLenum, GLfloat, GLsizei, GLsizeiptr, GLuint};
+use std::{borrow::Cow, ffi::c_void, mem, ops::Range, ptr};
+
+// position (3), normal (3), texture coordinates (2), tangent (4), color (4)
+pub const FLOATS_PER_VERTEX: usize = 16;
+
+/// A `Scene` uploaded to the GPU. Vertices are interleaved as
+/// position/normal/texture coordinates/tangent/color at attribute locations 0
+/// to 4, the color being white when the scene has no vertex colors, and the
+/// element buffer holds the triangles followed by the line and point
+/// elements. Triangles are kept in runs sharing a material, as index ranges.
+pub struct Mesh {
+    vao: GLuint,
+    vbo: GLuint,
+    ebo: GLuint,
+    index_type: GLenum,
+    index_size: usize,
+    triangle_indices: GLsizei,
+    line_indices: GLsizei,
+    point_indices: GLsizei,
+    materials: Vec<(Option<usize>, Range<usize>)>,
+}
+
+/// Element indices, 16 bits wide when every vertex fits.
+pub enum Elements<'a> {
+    U16(Cow<'a, [u16]>),
+    U32(Cow<'a, [u32]>),
+}
+
+/// What a `Mesh` is uploaded from: its interleaved vertices, its elements,
+/// and the runs of triangles sharing a material. The buffers may be borrowed,
+/// so that a cache can hand over its mapping as it is.
+pub struct MeshData<'a> {
+    pub vertices: Cow<'a, [f32]>,
+    pub indices: Elements<'a>,
+    pub triangle_indices: usize,
+    pub line_indices: usize,
+    pub point_indices: usize,
+    pub materials: Vec<(Option<usize>, Range<usize>)>,
+}
+
+fn push_vertex(scene: &Scene, corner: &FaceVertex, vertices: &mut Vec<f32>) {
+    let v = &scene.vertices[corner.vertex];
+    vertices.extend_from_slice(&[v.x, v.y, v.z]);
+    match corner.normal {
+        Some(i) => {
+            let n = &scene.normals[i];
+            vertices.extend_from_slice(&[n.x, n.y, n.z]);
+        }
+        None => vertices.extend_from_slice(&[0., 0., 0.]),
+    }
+    match corner.texture {
+        Some(i) => {
+            let t = &scene.texture_coordinates[i];
+            vertices.extend_from_slice(&[t.u, t.v]);
+        }
+        None => vertices.extend_from_slice(&[0., 0.]),
+    }
+}
+
+impl MeshData<'static> {
+    pub fn new(scene: &Scene, indexed: IndexedScene) -> Self {
+        let mut vertices: Vec<f32> = Vec::with_capacity(indexed.vertices.len() * FLOATS_PER_VERTEX);
+        let tangents = generate_tangents(scene, &indexed);
+        for (corner, tangent) in indexed.vertices.iter().zip(tangents) {
+            push_vertex(scene, corner, &mut vertices);
+            vertices.extend_from_slice(&tangent);
+            let color = scene.vertex_colors.get(corner.vertex);
+            vertices.extend_from_slice(color.unwrap_or(&[1., 1., 1., 1.]));
+        }
+        let mut materials: Vec<(Option<usize>, Range<usize>)> = Vec::new();
+        for (i, triangle) in scene.triangles.iter().enumerate() {
+            match materials.last_mut() {
+                Some((material, indices)) if *material == triangle.material => {
+                    indices.end = (i + 1) * 3
+                }
+                _ => materials.push((triangle.material, i * 3..(i + 1) * 3)),
+            }
+        }
+        Self {
+            vertices: Cow::Owned(vertices),
+            indices: match indexed.indices {
+                Indices::U16(indices) => Elements::U16(Cow::Owned(indices)),
+                Indices::U32(indices) => Elements::U32(Cow::Owned(indices)),
+            },
+            triangle_indices: indexed.triangle_indices,
+            line_indices: indexed.line_indices,
+            point_indices: indexed.point_indices,
+            materials,
+        }
+    }
+}
+
+impl MeshData<'_> {
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len() / FLOATS_PER_VERTEX
+    }
+
+    /// How many times each vertex is referenced on average, 1 meaning no
+    /// sharing at all. Closed smooth meshes get close to 6.
+    pub fn reuse_ratio(&self) -> f32 {
+        if self.vertex_count() == 0 {
+            return 0.;
+        }
+        let indices = self.triangle_indices + self.line_indices + self.point_indices;
+        indices as f32 / self.vertex_count() as f32
+    }
+}
+
+impl Mesh {
+    pub fn new(scene: &Scene) -> Self {
+        Self::upload(&MeshData::new(scene, IndexedScene::new(scene)))
+    }
+
+    pub fn upload(data: &MeshData) -> Self {
+        let (vao, vbo, ebo) = unsafe {
+            match &data.indices {
+                Elements::U16(indices) => upload(&data.vertices, indices),
+                Elements::U32(indices) => upload(&data.vertices, indices),
+            }
+        };
+        let (index_type, index_size) = match data.indices {
+            Elements::U16(_) => (gl::UNSIGNED_SHORT, mem::size_of::<u16>()),
+            Elements::U32(_) => (gl::UNSIGNED_INT, mem::size_of::<u32>()),
+        };
+        Self {
+            vao,
+            vbo,
+            ebo,
+            index_type,
+            index_size,
+            triangle_indices: data.triangle_indices as GLsizei,
+            line_indices: data.line_indices as GLsizei,
+            point_indices: data.point_indices as GLsizei,
+            materials: data.materials.clone(),
+        }
+    }
+
+    // Byte offset of the element at `indices` into the element buffer
+    fn offset(&self, indices: usize) -> *const c_void {
+        (indices * self.index_size) as *const c_void
+    }
+
+    /// Draws the triangles with whatever program is in use.
+    pub fn draw(&self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::DrawElements(
+                gl::TRIANGLES,
+                self.triangle_indices,
+                self.index_type,
+                ptr::null(),
+            );
+        }
+    }
+
+    /// Draws the triangles one run at a time, calling `bind` first with the
+    /// run's material, an index into `Scene::materials`.
+    pub fn draw_by_material(&self, mut bind: impl FnMut(Option<usize>)) {
+        unsafe { gl::BindVertexArray(self.vao) };
+        for (material, indices) in &self.materials {
+            bind(*material);
+            unsafe {
+                gl::DrawElements(
+                    gl::TRIANGLES,
+                    indices.len() as GLsizei,
+                    self.index_type,
+                    self.offset(indices.start),
+                );
+            }
+        }
+    }
+
+    /// Draws the line and point elements, which have no normals nor texture
+    /// coordinates and are meant for an unlit program.
+    pub fn draw_lines_and_points(&self) {
+        let offset = |indices: GLsizei| self.offset(indices as usize);
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::DrawElements(
+                gl::LINES,
+                self.line_indices,
+                self.index_type,
+                offset(self.triangle_indices),
+            );
+            gl::DrawElements(
+                gl::POINTS,
+                self.point_indices,
+                self.index_type,
+                offset(self.triangle_indices + self.line_indices),
+            );
+        }
+    }
+}
+
+impl Drop for Mesh {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteBuffers(1, &self.ebo);
+        }
+    }
+}
+
+unsafe fn upload<T>(vertices: &[f32], indices: &[T]) -> (GLuint, GLuint, GLuint) {
+    let (mut vao, mut vbo, mut ebo) = (0, 0, 0);
+    gl::GenVertexArrays(1, &mut vao);
+    gl::GenBuffers(1, &mut vbo);
+    gl::GenBuffers(1, &mut ebo);
+
+    gl::BindVertexArray(vao);
+
+    gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+    gl::BufferData(
+        gl::ARRAY_BUFFER,
+        mem::size_of_val(vertices) as GLsizeiptr,
+        vertices.as_ptr() as *const c_void,
+        gl::STATIC_DRAW,
+    );
+
+    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+    gl::BufferData(
+        gl::ELEMENT_ARRAY_BUFFER,
+        mem::size_of_val(indices) as GLsizeiptr,
+        indices.as_ptr() as *const c_void,
+        gl::STATIC_DRAW,
+    );
+
+    let stride = (FLOATS_PER_VERTEX * mem::size_of::<GLfloat>()) as GLsizei;
+    // position attrib
+    gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, ptr::null());
+    gl::EnableVertexAttribArray(0);
+    // normal attrib
+    gl::VertexAttribPointer(
+        1,
+        3,
+        gl::FLOAT,
+        gl::FALSE,
+        stride,
+        (3 * mem::size_of::<GLfloat>()) as *const c_void,
+    );
+    gl::EnableVertexAttribArray(1);
+    // texture coord attrib
+    gl::VertexAttribPointer(
+        2,
+        2,
+        gl::FLOAT,
+        gl::FALSE,
+        stride,
+        (6 * mem::size_of::<GLfloat>()) as *const c_void,
+    );
+    gl::EnableVertexAttribArray(2);
+    // tangent attrib, handedness in w
+    gl::VertexAttribPointer(
+        3,
+        4,
+        gl::FLOAT,
+        gl::FALSE,
+        stride,
+        (8 * mem::size_of::<GLfloat>()) as *const c_void,
+    );
+    gl::EnableVertexAttribArray(3);
+    // color attrib
+    gl::VertexAttribPointer(
+        4,
+        4,
+        gl::FLOAT,
+        gl::FALSE,
+        stride,
+        (12 * mem::size_of::<GLfloat>()) as *const c_void,
+    );
+    gl::EnableVertexAttribArray(4);
+
+    gl::BindVertexArray(0);
+    (vao, vbo, ebo)
+}
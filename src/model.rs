@@ -0,0 +1,179 @@
+use crate::indexing::IndexedScene;
+use crate::mesh::{Mesh, MeshData};
+use crate::normals::{self, NormalOptions};
+use crate::scene::{Material, Scene};
+use crate::shader::Shader;
+use crate::simplify;
+use crate::texture;
+use crate::tuple::{magnitude, vector, Vector};
+use crate::uvs::{self, Projection};
+use c_str_macro::c_str;
+use gl::types::GLuint;
+
+// Levels of detail, each with half the triangles of the one before
+const LOD_LEVELS: usize = 4;
+const LOD_RATIO: f32 = 0.5;
+// The model drops one level every this many bounding box diagonals away from
+// the camera
+const LOD_DISTANCE: f32 = 4.;
+
+/// Viewer settings that can be changed at runtime.
+pub struct ViewOptions {
+    pub normals: NormalOptions,
+    // Projection used to give texture coordinates to faces without any
+    pub uvs: Option<Projection>,
+    // Replace the diffuse map with a checkerboard
+    pub checker: bool,
+}
+
+/// A loaded OBJ ready to draw: its levels of detail on the GPU and the
+/// textures of its materials.
+pub struct Model {
+    pub lods: Vec<Scene>,
+    // Center of the bounding box, which the model should be placed around
+    pub center: Vector,
+    pub diagonal: f32,
+    meshes: Vec<Mesh>,
+    // One per material of the scene, then the default one for faces without
+    surfaces: Vec<Surface>,
+}
+
+// A material and its maps on the GPU
+struct Surface {
+    material: Material,
+    diffuse_map: GLuint,
+    specular_map: GLuint,
+    normal_map: Option<GLuint>,
+}
+
+// Generates what the file leaves out of `scene` and lays the result out for
+// the GPU
+fn mesh_data(scene: &Scene, options: &ViewOptions) -> MeshData<'static> {
+    let mut scene = scene.clone();
+    normals::generate_normals(&mut scene, options.normals);
+    if let Some(projection) = options.uvs {
+        uvs::generate_uvs(&mut scene, projection);
+    }
+    // Faces are drawn one material at a time, so they are gathered by
+    // material, in their order otherwise
+    scene.triangles.sort_by_key(|triangle| triangle.material);
+    MeshData::new(&scene, IndexedScene::new(&scene))
+}
+
+/// Simplifies `scene` into its levels of detail and lays out the mesh of
+/// each, to be uploaded by `Model::new` or cached.
+pub fn prepare(scene: &Scene, options: &ViewOptions) -> (Vec<Scene>, Vec<MeshData<'static>>) {
+    let lods = simplify::lod_chain(scene, LOD_LEVELS, LOD_RATIO);
+    let meshes = lods.iter().map(|lod| mesh_data(lod, options)).collect();
+    (lods, meshes)
+}
+
+impl Surface {
+    fn new(material: Material) -> Self {
+        let diffuse_map = texture::load_texture_or_white(material.diffuse_map.as_deref());
+        let specular_map = texture::load_texture_or_white(material.specular_map.as_deref());
+        // map_Bump is taken as a tangent space normal map
+        let normal_map = match material.bump_map.as_deref().map(texture::load_texture) {
+            Some(Ok(normal_map)) => Some(normal_map),
+            Some(Err(e)) => {
+                eprintln!("Failed to load normal map: {}", e);
+                None
+            }
+            None => None,
+        };
+        Self {
+            material,
+            diffuse_map,
+            specular_map,
+            normal_map,
+        }
+    }
+
+    // Sets the material's uniforms and binds its maps, `checker` replacing
+    // the diffuse map when given
+    fn bind(&self, shader: &Shader, checker: Option<GLuint>) {
+        shader.set_float(
+            c_str!("material.shininess"),
+            self.material.shininess.max(1.),
+        );
+        shader.set_color(c_str!("material.ambientColor"), self.material.ambient);
+        shader.set_color(c_str!("material.diffuseColor"), self.material.diffuse);
+        shader.set_color(c_str!("material.specularColor"), self.material.specular);
+        shader.set_int(
+            c_str!("material.hasNormalMap"),
+            self.normal_map.is_some() as i32,
+        );
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, checker.unwrap_or(self.diffuse_map));
+            gl::ActiveTexture(gl::TEXTURE1);
+            gl::BindTexture(gl::TEXTURE_2D, self.specular_map);
+            if let Some(normal_map) = self.normal_map {
+                gl::ActiveTexture(gl::TEXTURE2);
+                gl::BindTexture(gl::TEXTURE_2D, normal_map);
+            }
+        }
+    }
+}
+
+impl Drop for Surface {
+    fn drop(&mut self) {
+        let textures: Vec<GLuint> = [self.diffuse_map, self.specular_map]
+            .into_iter()
+            .chain(self.normal_map)
+            .collect();
+        unsafe { gl::DeleteTextures(textures.len() as i32, textures.as_ptr()) };
+    }
+}
+
+impl Model {
+    /// Uploads `meshes`, one per level of detail, made from `lods` by
+    /// `prepare` or read back from the cache. `lods` starts with `scene`
+    /// itself, and is kept to rebuild the meshes without simplifying again.
+    pub fn new(scene: &Scene, lods: Vec<Scene>, meshes: &[MeshData]) -> Self {
+        let (center, diagonal) = match scene.bounding_box() {
+            Some(bounds) => (bounds.center(), magnitude(bounds.size())),
+            None => (vector(0., 0., 0.), 1.),
+        };
+        let surfaces = scene
+            .materials
+            .iter()
+            .cloned()
+            .chain([Material::new("default")])
+            .map(Surface::new)
+            .collect();
+
+        let meshes = meshes.iter().map(Mesh::upload).collect();
+        Self {
+            lods,
+            center,
+            diagonal,
+            meshes,
+            surfaces,
+        }
+    }
+
+    /// Regenerates the meshes after `options` changed.
+    pub fn rebuild(&mut self, options: &ViewOptions) {
+        self.meshes = self
+            .lods
+            .iter()
+            .map(|lod| Mesh::upload(&mesh_data(lod, options)))
+            .collect();
+    }
+
+    /// Draws the level of detail fitting `distance` from the camera to the
+    /// model's center, with `shader` in use and its model matrix set.
+    /// `checker` replaces the diffuse map when given.
+    pub fn draw(&self, shader: &Shader, distance: f32, checker: Option<GLuint>) {
+        let level = (distance / (self.diagonal * LOD_DISTANCE)) as usize;
+        let default = self.surfaces.len() - 1;
+        self.meshes[level.min(self.meshes.len() - 1)].draw_by_material(|material| {
+            self.surfaces[material.unwrap_or(default)].bind(shader, checker)
+        });
+    }
+
+    pub fn draw_lines_and_points(&self) {
+        self.meshes[0].draw_lines_and_points();
+    }
+}
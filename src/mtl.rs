@@ -0,0 +1,294 @@
+use crate::parser::{LineContext, ParseError, ParseErrorKind};
+use crate::scene::{Color, Material};
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+};
+
+/// MTL material library parser, the companion of the OBJ `Parser`.
+pub struct MaterialParser<R: BufRead> {
+    path: String,
+    directory: PathBuf,
+    reader: R,
+}
+
+enum LineType {
+    Unimplemented,
+    Unknown,
+    Comment,
+    NewMaterial,
+    Ambient,
+    Diffuse,
+    Specular,
+    Emissive,
+    Shininess,
+    OpticalDensity,
+    Dissolve,
+    Transparency,
+    Illumination,
+    DiffuseMap,
+    SpecularMap,
+    BumpMap,
+    DissolveMap,
+}
+
+impl MaterialParser<BufReader<File>> {
+    pub fn new(filename: &str, directory: &Path) -> Result<Self, ParseError> {
+        let file = File::open(filename).map_err(|e| ParseError::io(filename, 0, e))?;
+        Ok(Self::from_reader(BufReader::new(file), filename, directory))
+    }
+}
+
+impl<R: BufRead> MaterialParser<R> {
+    /// Texture maps are resolved relative to `directory`, the OBJ file's one.
+    pub fn from_reader(reader: R, path: &str, directory: &Path) -> Self {
+        Self {
+            path: path.to_string(),
+            directory: directory.to_path_buf(),
+            reader,
+        }
+    }
+
+    fn get_line_type(&self, keyword: &str) -> LineType {
+        if keyword.starts_with('#') {
+            return LineType::Comment;
+        }
+        match keyword {
+            "newmtl" => LineType::NewMaterial,
+            "Ka" => LineType::Ambient,
+            "Kd" => LineType::Diffuse,
+            "Ks" => LineType::Specular,
+            "Ke" => LineType::Emissive,
+            "Ns" => LineType::Shininess,
+            "Ni" => LineType::OpticalDensity,
+            "d" => LineType::Dissolve,
+            "Tr" => LineType::Transparency,
+            "illum" => LineType::Illumination,
+            "map_Kd" => LineType::DiffuseMap,
+            "map_Ks" => LineType::SpecularMap,
+            "map_Bump" | "map_bump" | "bump" => LineType::BumpMap,
+            "map_d" => LineType::DissolveMap,
+            "Tf" | "sharpness" | "map_Ka" | "map_Ke" | "map_Ns" | "map_aat" | "disp" | "decal"
+            | "refl" | "norm" | "Pr" | "Pm" | "Ps" | "Pc" | "Pcr" | "aniso" | "anisor"
+            | "map_Pr" | "map_Pm" | "map_Ps" => LineType::Unimplemented,
+            _ => LineType::Unknown,
+        }
+    }
+
+    pub fn generate_materials(mut self) -> Result<Vec<Material>, ParseError> {
+        let mut materials: Vec<Material> = Vec::new();
+        let mut line = String::new();
+        let mut number: usize = 0;
+        // Skipped keywords are only reported once per library
+        let mut skipped: HashSet<String> = HashSet::new();
+
+        loop {
+            line.clear();
+            number += 1;
+            let read = self
+                .reader
+                .read_line(&mut line)
+                .map_err(|e| ParseError::io(&self.path, number, e))?;
+            if read == 0 {
+                break;
+            }
+            let line = line.trim_end();
+            let split_line: Vec<&str> = line.split_whitespace().collect();
+            let Some(keyword) = split_line.first() else {
+                continue;
+            };
+            let ctx = LineContext {
+                path: &self.path,
+                number,
+                line,
+            };
+            let line_type = self.get_line_type(keyword);
+            match line_type {
+                LineType::Unimplemented => {
+                    if skipped.insert(keyword.to_string()) {
+                        eprintln!("{}: unimplemented feature {}", self.path, keyword);
+                    }
+                    continue;
+                }
+                // Exporters add their own keywords, which should not cost us
+                // the whole library
+                LineType::Unknown => {
+                    if skipped.insert(keyword.to_string()) {
+                        eprintln!("{}: unknown directive {}, skipped", self.path, keyword);
+                    }
+                    continue;
+                }
+                LineType::Comment => continue,
+                LineType::NewMaterial => {
+                    let name = self.parse_name(&ctx, &split_line)?;
+                    materials.push(Material::new(&name));
+                    continue;
+                }
+                _ => {}
+            }
+            let Some(material) = materials.last_mut() else {
+                return Err(ctx.error(ParseErrorKind::OutsideMaterial, keyword));
+            };
+            match line_type {
+                LineType::Ambient => material.ambient = self.parse_color(&ctx, &split_line)?,
+                LineType::Diffuse => material.diffuse = self.parse_color(&ctx, &split_line)?,
+                LineType::Specular => material.specular = self.parse_color(&ctx, &split_line)?,
+                LineType::Emissive => material.emissive = self.parse_color(&ctx, &split_line)?,
+                LineType::Shininess => material.shininess = self.parse_float(&ctx, &split_line)?,
+                LineType::OpticalDensity => {
+                    material.optical_density = self.parse_float(&ctx, &split_line)?
+                }
+                LineType::Dissolve => material.dissolve = self.parse_float(&ctx, &split_line)?,
+                LineType::Transparency => {
+                    material.dissolve = 1. - self.parse_float(&ctx, &split_line)?
+                }
+                LineType::Illumination => {
+                    material.illumination = self.parse_illumination(&ctx, &split_line)?
+                }
+                LineType::DiffuseMap => {
+                    material.diffuse_map = Some(self.parse_map(&ctx, &split_line)?)
+                }
+                LineType::SpecularMap => {
+                    material.specular_map = Some(self.parse_map(&ctx, &split_line)?)
+                }
+                LineType::BumpMap => material.bump_map = Some(self.parse_map(&ctx, &split_line)?),
+                LineType::DissolveMap => {
+                    material.dissolve_map = Some(self.parse_map(&ctx, &split_line)?)
+                }
+                _ => {}
+            }
+        }
+        Ok(materials)
+    }
+
+    fn missing(&self, ctx: &LineContext, what: &str) -> ParseError {
+        ctx.error_at_end(ParseErrorKind::MissingComponent(what.to_string()))
+    }
+
+    fn parse_number(&self, ctx: &LineContext, token: &str) -> Result<f32, ParseError> {
+        token
+            .parse::<f32>()
+            .map_err(|_| ctx.error(ParseErrorKind::BadNumber, token))
+    }
+
+    // newmtl name
+    fn parse_name(&self, ctx: &LineContext, split_line: &[&str]) -> Result<String, ParseError> {
+        if split_line.len() < 2 {
+            return Err(self.missing(ctx, "material name"));
+        }
+        Ok(split_line[1..].join(" "))
+    }
+
+    // Ns/Ni/d/Tr value
+    fn parse_float(&self, ctx: &LineContext, split_line: &[&str]) -> Result<f32, ParseError> {
+        let token = split_line
+            .get(1)
+            .ok_or_else(|| self.missing(ctx, "value"))?;
+        self.parse_number(ctx, token)
+    }
+
+    // Ka/Kd/Ks/Ke r [g b], where a lone r stands for a grey
+    fn parse_color(&self, ctx: &LineContext, split_line: &[&str]) -> Result<Color, ParseError> {
+        let r = self.parse_float(ctx, split_line)?;
+        match (split_line.get(2), split_line.get(3)) {
+            (None, _) => Ok(Color::new(r, r, r)),
+            (Some(_), None) => Err(self.missing(ctx, "blue component in color")),
+            (Some(g), Some(b)) => Ok(Color::new(
+                r,
+                self.parse_number(ctx, g)?,
+                self.parse_number(ctx, b)?,
+            )),
+        }
+    }
+
+    // illum model
+    fn parse_illumination(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+    ) -> Result<u32, ParseError> {
+        let token = split_line
+            .get(1)
+            .ok_or_else(|| self.missing(ctx, "illumination model"))?;
+        token
+            .parse::<u32>()
+            .map_err(|_| ctx.error(ParseErrorKind::BadNumber, token))
+    }
+
+    // map_* [-option args...] file, where the file name is the rest of the
+    // line and may contain spaces. Options are skipped: -mm takes two
+    // arguments, -o, -s and -t one to three numbers, and the others one.
+    fn parse_map(&self, ctx: &LineContext, split_line: &[&str]) -> Result<PathBuf, ParseError> {
+        let mut i = 1;
+        while let Some(option) = split_line.get(i).filter(|token| token.starts_with('-')) {
+            let (min, max) = match *option {
+                "-mm" => (2, 2),
+                "-o" | "-s" | "-t" => (1, 3),
+                _ => (1, 1),
+            };
+            i += 1;
+            for taken in 0..max {
+                match split_line.get(i) {
+                    Some(token) if taken < min || token.parse::<f32>().is_ok() => i += 1,
+                    _ => break,
+                }
+            }
+        }
+        let file = split_line
+            .get(i)
+            .ok_or_else(|| self.missing(ctx, "texture file"))?;
+        // `file` is a slice of the line, which runs to its end
+        let offset = file.as_ptr() as usize - ctx.line.as_ptr() as usize;
+        Ok(self.directory.join(&ctx.line[offset..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Vec<Material> {
+        MaterialParser::from_reader(text.as_bytes(), "<test>", Path::new("models"))
+            .generate_materials()
+            .unwrap()
+    }
+
+    #[test]
+    fn unknown_keywords_are_skipped() {
+        let materials = parse(
+            "newmtl red\nKd 1 0 0\nvendor_gloss 0.5\nmap_Kd red.png\nnewmtl blue\nKd 0 0 1\n",
+        );
+        assert_eq!(materials.len(), 2);
+        assert_eq!(materials[0].diffuse.r, 1.);
+        assert_eq!(
+            materials[0].diffuse_map,
+            Some(PathBuf::from("models/red.png"))
+        );
+        assert_eq!(materials[1].diffuse.b, 1.);
+    }
+
+    #[test]
+    fn map_file_names_keep_their_spaces() {
+        let materials = parse(
+            "newmtl m\n\
+             map_Kd my texture.png\n\
+             map_Ks -o 0.5 0.5 -clamp on -mm 0 1 shiny  bits.png\n\
+             map_Bump -bm 2 -s 2 normals/wall.png\n",
+        );
+        let material = &materials[0];
+        assert_eq!(
+            material.diffuse_map,
+            Some(PathBuf::from("models/my texture.png"))
+        );
+        assert_eq!(
+            material.specular_map,
+            Some(PathBuf::from("models/shiny  bits.png"))
+        );
+        assert_eq!(
+            material.bump_map,
+            Some(PathBuf::from("models/normals/wall.png"))
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::scene::{Normal, Scene, Triangle};
+use crate::tuple::{cross, dot, magnitude, vector, Vector};
+use std::collections::HashMap;
+
+/// How the faces around a vertex are combined into its normal.
+#[derive(Clone, Copy)]
+pub enum Weighting {
+    // Bigger faces pull harder
+    Area,
+    // Each face counts for the angle it makes at the vertex, so the result
+    // does not depend on how the surface is tessellated
+    Angle,
+}
+
+#[derive(Clone, Copy)]
+pub enum Shading {
+    /// Smooth inside the file's smoothing groups, flat where smoothing is off.
+    Groups(Weighting),
+    /// One normal per face.
+    Flat,
+    /// Everything smooth, whatever the smoothing groups say.
+    Smooth(Weighting),
+}
+
+#[derive(Clone, Copy)]
+pub struct NormalOptions {
+    pub shading: Shading,
+    // In degrees. Faces meeting at a sharper angle than this do not smooth
+    // into each other, keeping hard edges hard.
+    pub crease_angle: Option<f32>,
+}
+
+impl Default for NormalOptions {
+    fn default() -> Self {
+        Self {
+            shading: Shading::Groups(Weighting::Area),
+            crease_angle: None,
+        }
+    }
+}
+
+fn position(scene: &Scene, index: usize) -> Vector {
+    let v = &scene.vertices[index];
+    vector(v.x, v.y, v.z)
+}
+
+// Not normalized: its length is twice the triangle's area, so summing these
+// weights each face by its area.
+pub fn face_normal(scene: &Scene, triangle: &Triangle) -> Vector {
+    let [a, b, c] = triangle
+        .corners
+        .map(|corner| position(scene, corner.vertex));
+    cross(b - a, c - a)
+}
+
+// `v` scaled to length 1, left alone when it is zero
+pub fn unit(v: Vector) -> Vector {
+    let length = magnitude(v);
+    if length == 0. {
+        return v;
+    }
+    v * (1. / length)
+}
+
+fn to_normal(v: Vector) -> Normal {
+    let length = magnitude(v);
+    if length == 0. {
+        // Degenerate faces still need a usable normal for lighting
+        return Normal::new(0., 0., 1.);
+    }
+    Normal::new(v.x / length, v.y / length, v.z / length)
+}
+
+// Interior angle of the triangle at `corner`
+fn corner_angle(scene: &Scene, triangle: &Triangle, corner: usize) -> f32 {
+    let vertex = |i: usize| position(scene, triangle.corners[(corner + i) % 3].vertex);
+    let (a, b) = (unit(vertex(1) - vertex(0)), unit(vertex(2) - vertex(0)));
+    dot(a, b).clamp(-1., 1.).acos()
+}
+
+fn needs_normals(triangle: &Triangle) -> bool {
+    triangle
+        .corners
+        .iter()
+        .any(|corner| corner.normal.is_none())
+}
+
+/// Fills in the normals of every face corner missing one, leaving the ones
+/// the file gives alone. Flat faces get their own normal; smoothed ones
+/// share, per vertex, the weighted average of the faces around it that are in
+/// the same smoothing group (with `Shading::Groups`) and within the crease
+/// angle, so vertices on a hard edge end up split into several normals.
+/// Identical normals are stored once. `NormalOptions::default()` follows the
+/// file's smoothing groups.
+pub fn generate_normals(scene: &mut Scene, options: NormalOptions) {
+    let pending: Vec<usize> = (0..scene.triangles.len())
+        .filter(|&i| needs_normals(&scene.triangles[i]))
+        .collect();
+    let face_normals: HashMap<usize, Vector> = pending
+        .iter()
+        .map(|&i| (i, face_normal(scene, &scene.triangles[i])))
+        .collect();
+
+    // Which smoothing set a face belongs to, None when it is flat
+    let smoothing = |triangle: &Triangle| match options.shading {
+        Shading::Flat => None,
+        Shading::Smooth(_) => Some(0),
+        Shading::Groups(_) if triangle.smoothing_group == 0 => None,
+        Shading::Groups(_) => Some(triangle.smoothing_group),
+    };
+    let weighting = match options.shading {
+        Shading::Groups(weighting) | Shading::Smooth(weighting) => weighting,
+        Shading::Flat => Weighting::Area,
+    };
+
+    // Every (triangle, corner) smoothing into each (vertex, smoothing set)
+    let mut incident: HashMap<(usize, u32), Vec<(usize, usize)>> = HashMap::new();
+    for &i in pending.iter() {
+        let triangle = &scene.triangles[i];
+        let Some(set) = smoothing(triangle) else {
+            continue;
+        };
+        for (c, corner) in triangle.corners.iter().enumerate() {
+            incident
+                .entry((corner.vertex, set))
+                .or_default()
+                .push((i, c));
+        }
+    }
+    let contribution = |i: usize, c: usize| match weighting {
+        Weighting::Area => face_normals[&i],
+        Weighting::Angle => unit(face_normals[&i]) * corner_angle(scene, &scene.triangles[i], c),
+    };
+    let crease_cosine = options.crease_angle.map(|angle| angle.to_radians().cos());
+
+    let mut normals: Vec<[Vector; 3]> = Vec::with_capacity(pending.len());
+    for &i in pending.iter() {
+        let triangle = &scene.triangles[i];
+        let Some(set) = smoothing(triangle) else {
+            normals.push([face_normals[&i]; 3]);
+            continue;
+        };
+        let own = unit(face_normals[&i]);
+        normals.push([0, 1, 2].map(|c| {
+            incident[&(triangle.corners[c].vertex, set)]
+                .iter()
+                .filter(|&&(j, _)| match crease_cosine {
+                    Some(cosine) => j == i || dot(own, unit(face_normals[&j])) >= cosine,
+                    None => true,
+                })
+                .fold(vector(0., 0., 0.), |sum, &(j, k)| sum + contribution(j, k))
+        }));
+    }
+
+    let mut indices: HashMap<[u32; 3], usize> = HashMap::new();
+    for (&i, corner_normals) in pending.iter().zip(normals) {
+        for (c, normal) in corner_normals.into_iter().enumerate() {
+            if scene.triangles[i].corners[c].normal.is_some() {
+                continue;
+            }
+            let normal = to_normal(normal);
+            let key = [normal.x, normal.y, normal.z].map(f32::to_bits);
+            let index = *indices.entry(key).or_insert_with(|| {
+                scene.normals.push(normal);
+                scene.normals.len() - 1
+            });
+            scene.triangles[i].corners[c].normal = Some(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    #[test]
+    fn only_corners_without_normals_get_one() {
+        let mut scene = Parser::from_reader(
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 1 0 0\nf 1//1 2 3//1\n".as_bytes(),
+            "<test>",
+        )
+        .generate_scene()
+        .unwrap();
+        generate_normals(&mut scene, NormalOptions::default());
+        let [a, b, c] = scene.triangles[0].corners.map(|corner| corner.normal);
+        assert_eq!((a, c), (Some(0), Some(0)));
+        let n = &scene.normals[b.unwrap()];
+        assert_eq!((n.x, n.y, n.z), (0., 0., 1.));
+        assert_eq!(scene.normals.len(), 2);
+    }
+}
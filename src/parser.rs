@@ -1,57 +1,621 @@
-use crate::scene::{Scene, Vertex};
+mod parallel;
+
+use crate::mtl::MaterialParser;
+use crate::scene::{
+    FaceVertex, Group, Material, Normal, Object, Scene, TextureCoordinate, Triangle, Vertex,
+};
 use std::{
+    collections::HashSet,
+    error, fmt,
     fs::File,
-    io::{BufRead, BufReader, Error, ErrorKind},
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
 };
 
-pub struct Parser {
-    lines: Vec<String>,
+/// OBJ parser reading its input line by line from any `BufRead`, so large
+/// files never need to be held in memory as text.
+pub struct Parser<R: BufRead> {
+    path: String,
+    directory: PathBuf,
+    reader: R,
+}
+
+pub enum ParseErrorKind {
+    Io(io::Error),
+    BadNumber,
+    IndexOutOfRange,
+    UnknownDirective,
+    MissingComponent(String),
+    TooManyComponents(String),
+    MalformedFace,
+    OutsideMaterial,
+    Unsupported(String),
+    Expected(String),
+}
+
+/// Where and why a file failed to parse. `line` and `column` are 1-based (0
+/// when the error is not tied to a line) and `token` is the offending piece of
+/// text, empty when something is missing. Binary formats without lines may
+/// name the offending part of the file in `token` instead.
+pub struct ParseError {
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub kind: ParseErrorKind,
 }
 
 enum LineType {
-    Error(String),
     Unimplemented,
+    Unknown,
     Comment,
     Vertices,
+    TextureCoordinates,
+    Normals,
+    Faces,
+    Objects,
+    Groups,
+    SmoothingGroups,
+    MaterialLibraries,
+    UseMaterial,
+    Lines,
+    Points,
+}
+
+// Directives that apply to every face after them, and the keywords already
+// reported as skipped, which are only reported once per file
+struct ParseState {
+    smoothing_group: u32,
+    material: Option<usize>,
+    skipped: HashSet<String>,
 }
 
-impl Parser {
-    pub fn new(filename: &str) -> Result<Self, Error> {
-        let file = File::open(filename)?;
-        let reader = BufReader::new(file);
+impl ParseState {
+    fn new() -> Self {
+        Self {
+            smoothing_group: 0,
+            material: None,
+            skipped: HashSet::new(),
+        }
+    }
+}
+
+// Position of the line being parsed, used to build errors pointing at a token.
+pub(crate) struct LineContext<'a> {
+    pub path: &'a str,
+    pub number: usize,
+    pub line: &'a str,
+}
+
+impl<'a> LineContext<'a> {
+    // `token` must be a slice of `self.line`
+    pub fn error(&self, kind: ParseErrorKind, token: &str) -> ParseError {
+        let offset = token.as_ptr() as usize - self.line.as_ptr() as usize;
+        ParseError {
+            path: self.path.to_string(),
+            line: self.number,
+            column: offset + 1,
+            token: token.to_string(),
+            kind,
+        }
+    }
+
+    pub fn error_at_end(&self, kind: ParseErrorKind) -> ParseError {
+        self.error(kind, &self.line[self.line.trim_end().len()..])
+    }
+}
 
-        let mut lines: Vec<String> = Vec::new();
-        for line in reader.lines() {
-            lines.push(line?);
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::Io(e) => write!(f, "{}", e),
+            ParseErrorKind::BadNumber => write!(f, "invalid number"),
+            ParseErrorKind::IndexOutOfRange => write!(f, "index out of range"),
+            ParseErrorKind::UnknownDirective => write!(f, "unknown directive"),
+            ParseErrorKind::MissingComponent(what) => write!(f, "missing {}", what),
+            ParseErrorKind::TooManyComponents(what) => write!(f, "too many components in {}", what),
+            ParseErrorKind::MalformedFace => write!(f, "malformed face"),
+            ParseErrorKind::OutsideMaterial => write!(f, "material property before any newmtl"),
+            ParseErrorKind::Unsupported(what) => write!(f, "unsupported {}", what),
+            ParseErrorKind::Expected(what) => write!(f, "expected {}", what),
         }
-        Ok(Self { lines })
     }
+}
 
-    fn get_line_type(&self, line: &String) -> LineType {
-        match line.as_str() {
-            "#" => return LineType::Comment,
-            "v" => return LineType::Vertices,
-            "" => return LineType::Error("Line ended".to_string()),
-            _ => return LineType::Unimplemented,
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.path, self.kind)?;
+        } else {
+            write!(
+                f,
+                "{}:{}:{}: {}",
+                self.path, self.line, self.column, self.kind
+            )?;
+        }
+        if !self.token.is_empty() {
+            write!(f, " '{}'", self.token)?;
         }
+        Ok(())
     }
+}
 
-    pub fn generate_scene(&self) -> Result<Scene, Error> {
-        let mut vertices: Vec<Vertex> = Vec::new();
+impl fmt::Debug for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl error::Error for ParseError {}
 
-        for line in self.lines.iter() {
+impl ParseError {
+    pub(crate) fn io(path: &str, line: usize, e: io::Error) -> Self {
+        ParseError {
+            path: path.to_string(),
+            line,
+            column: 0,
+            token: String::new(),
+            kind: ParseErrorKind::Io(e),
+        }
+    }
+}
+
+impl Parser<BufReader<File>> {
+    pub fn new(filename: &str) -> Result<Self, ParseError> {
+        let file = File::open(filename).map_err(|e| ParseError::io(filename, 0, e))?;
+        Ok(Self::from_reader(BufReader::new(file), filename))
+    }
+}
+
+impl<R: BufRead> Parser<R> {
+    /// `path` labels errors, e.g. "<stdin>", and its directory is where
+    /// material libraries and textures are looked up.
+    pub fn from_reader(reader: R, path: &str) -> Self {
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+        Self {
+            path: path.to_string(),
+            directory: directory.to_path_buf(),
+            reader,
+        }
+    }
+
+    fn get_line_type(&self, keyword: &str) -> LineType {
+        if keyword.starts_with('#') {
+            return LineType::Comment;
+        }
+        match keyword {
+            "v" => LineType::Vertices,
+            "vt" => LineType::TextureCoordinates,
+            "vn" => LineType::Normals,
+            "f" => LineType::Faces,
+            "o" => LineType::Objects,
+            "g" => LineType::Groups,
+            "s" => LineType::SmoothingGroups,
+            "mtllib" => LineType::MaterialLibraries,
+            "usemtl" => LineType::UseMaterial,
+            "l" => LineType::Lines,
+            "p" => LineType::Points,
+            "vp" | "mg" | "maplib" | "usemap" | "lod" | "bevel" | "c_interp" | "d_interp"
+            | "shadow_obj" | "trace_obj" | "ctech" | "stech" | "cstype" | "deg" | "bmat"
+            | "step" | "curv" | "curv2" | "surf" | "parm" | "trim" | "hole" | "scrv" | "sp"
+            | "end" | "con" | "call" | "csh" => LineType::Unimplemented,
+            _ => LineType::Unknown,
+        }
+    }
+
+    pub fn generate_scene(mut self) -> Result<Scene, ParseError> {
+        let mut scene = Scene::new();
+        let mut line = String::new();
+        let mut number: usize = 0;
+        let mut state = ParseState::new();
+
+        loop {
+            line.clear();
+            number += 1;
+            let read = self
+                .reader
+                .read_line(&mut line)
+                .map_err(|e| ParseError::io(&self.path, number, e))?;
+            if read == 0 {
+                break;
+            }
+            let line = line.trim_end();
             let split_line: Vec<&str> = line.split_whitespace().collect();
-            match self.get_line_type(&split_line[0].to_string()) {
-                LineType::Unimplemented => println!("Unimplemented feature {}", split_line[0]),
-                LineType::Error(_) => {
-                    return Err(Error::new(ErrorKind::UnexpectedEof, line.to_string()))
+            if split_line.is_empty() {
+                continue;
+            }
+            let ctx = LineContext {
+                path: &self.path,
+                number,
+                line,
+            };
+            self.parse_line(&ctx, &split_line, &mut scene, &mut state)?;
+        }
+        self.close_object(&mut scene);
+        Ok(scene)
+    }
+
+    // Applies one non-blank line to the scene being built
+    fn parse_line(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+        scene: &mut Scene,
+        state: &mut ParseState,
+    ) -> Result<(), ParseError> {
+        match self.get_line_type(split_line[0]) {
+            // Free-form geometry and vendor extensions are skipped, the
+            // rest of the file usually being enough to show the model
+            LineType::Unimplemented => {
+                if state.skipped.insert(split_line[0].to_string()) {
+                    eprintln!("{}: unimplemented feature {}", self.path, split_line[0]);
+                }
+            }
+            LineType::Unknown => {
+                if state.skipped.insert(split_line[0].to_string()) {
+                    eprintln!(
+                        "{}: unknown directive {}, skipped",
+                        self.path, split_line[0]
+                    );
+                }
+            }
+            LineType::Comment => {}
+            LineType::Vertices => scene.vertices.push(self.parse_vertex(ctx, split_line)?),
+            LineType::TextureCoordinates => scene
+                .texture_coordinates
+                .push(self.parse_texture_coordinate(ctx, split_line)?),
+            LineType::Normals => scene.normals.push(self.parse_normal(ctx, split_line)?),
+            LineType::Faces => {
+                let counts = self.element_counts(scene);
+                let face = self.parse_face(ctx, split_line, counts)?;
+                self.add_face(&face, scene, state);
+            }
+            LineType::Objects => {
+                let name = self.parse_object_name(ctx, split_line)?;
+                self.close_object(scene);
+                scene
+                    .objects
+                    .push(Object::new(&name, scene.triangles.len()));
+            }
+            LineType::SmoothingGroups => {
+                state.smoothing_group = self.parse_smoothing_group(ctx, split_line)?
+            }
+            LineType::MaterialLibraries => self.load_material_libraries(split_line, scene),
+            LineType::UseMaterial => {
+                let name = self.parse_material_name(ctx, split_line)?;
+                state.material = Some(match scene.material_index(&name) {
+                    Some(index) => index,
+                    None => scene.add_material(Material::new(&name)),
+                });
+            }
+            LineType::Lines => {
+                let counts = self.element_counts(scene);
+                let vertices = self.parse_element(ctx, split_line, counts, 2, "line")?;
+                for segment in vertices.windows(2) {
+                    scene.lines.push([segment[0], segment[1]]);
+                }
+            }
+            LineType::Points => {
+                let counts = self.element_counts(scene);
+                let vertices = self.parse_element(ctx, split_line, counts, 1, "point")?;
+                scene.points.extend(vertices);
+            }
+            LineType::Groups => {
+                let names = self.parse_group_names(split_line);
+                self.close_group(scene);
+                if scene.objects.is_empty() {
+                    scene.objects.push(Object::new("", scene.triangles.len()));
+                }
+                let start = scene.triangles.len();
+                if let Some(object) = scene.objects.last_mut() {
+                    object.groups.push(Group::new(names, start));
                 }
-                LineType::Comment => continue,
-                LineType::Vertices => vertices.push(Vertex::new(1., 1., 1., 1.)),
             }
         }
-        Ok(Scene {
-            vertices
+        Ok(())
+    }
+
+    fn add_face(&self, face: &[FaceVertex], scene: &mut Scene, state: &ParseState) {
+        if scene.objects.is_empty() {
+            scene.objects.push(Object::new("", scene.triangles.len()));
+        }
+        let triangles = &mut scene.triangles;
+        self.triangulate(face, state.smoothing_group, state.material, triangles);
+    }
+
+    // o name, where the name may contain spaces
+    fn parse_object_name(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+    ) -> Result<String, ParseError> {
+        if split_line.len() < 2 {
+            return Err(
+                ctx.error_at_end(ParseErrorKind::MissingComponent("object name".to_string()))
+            );
+        }
+        Ok(split_line[1..].join(" "))
+    }
+
+    // mtllib file..., relative to the OBJ file. A library that cannot be read
+    // only costs us its materials, so it is reported without failing the scene.
+    fn load_material_libraries(&self, split_line: &[&str], scene: &mut Scene) {
+        for name in split_line[1..].iter() {
+            let path = self.directory.join(name);
+            let path = path.to_string_lossy();
+            match MaterialParser::new(&path, &self.directory).and_then(|p| p.generate_materials()) {
+                Ok(materials) => {
+                    for material in materials {
+                        scene.add_material(material);
+                    }
+                }
+                Err(e) => eprintln!("Could not load material library: {}", e),
+            }
+        }
+    }
+
+    // usemtl name
+    fn parse_material_name(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+    ) -> Result<String, ParseError> {
+        if split_line.len() < 2 {
+            return Err(ctx.error_at_end(ParseErrorKind::MissingComponent(
+                "material name".to_string(),
+            )));
+        }
+        Ok(split_line[1..].join(" "))
+    }
+
+    // s id | s off, where off and 0 both disable smoothing
+    fn parse_smoothing_group(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+    ) -> Result<u32, ParseError> {
+        let token = split_line.get(1).ok_or_else(|| {
+            ctx.error_at_end(ParseErrorKind::MissingComponent(
+                "smoothing group".to_string(),
+            ))
+        })?;
+        self.check_component_count(ctx, split_line, 1, "smoothing group")?;
+        match *token {
+            "off" => Ok(0),
+            _ => token
+                .parse::<u32>()
+                .map_err(|_| ctx.error(ParseErrorKind::BadNumber, token)),
+        }
+    }
+
+    // g [name...], defaulting to the "default" group
+    fn parse_group_names(&self, split_line: &[&str]) -> Vec<String> {
+        if split_line.len() < 2 {
+            return vec!["default".to_string()];
+        }
+        split_line[1..]
+            .iter()
+            .map(|name| name.to_string())
+            .collect()
+    }
+
+    // Ends the current group at the last parsed triangle, dropping it if it
+    // ended up without faces.
+    fn close_group(&self, scene: &mut Scene) {
+        let end = scene.triangles.len();
+        if let Some(object) = scene.objects.last_mut() {
+            if let Some(group) = object.groups.last_mut() {
+                group.triangles.end = end;
+                if group.triangles.is_empty() {
+                    object.groups.pop();
+                }
+            }
+        }
+    }
+
+    fn close_object(&self, scene: &mut Scene) {
+        self.close_group(scene);
+        let end = scene.triangles.len();
+        if let Some(object) = scene.objects.last_mut() {
+            object.triangles.end = end;
+            if object.is_empty() {
+                scene.objects.pop();
+            }
+        }
+    }
+
+    fn parse_coordinate(
+        &self,
+        ctx: &LineContext,
+        token: Option<&&str>,
+        name: &str,
+        element: &str,
+    ) -> Result<f32, ParseError> {
+        let token = token.ok_or_else(|| {
+            ctx.error_at_end(ParseErrorKind::MissingComponent(format!(
+                "{} component in {}",
+                name, element
+            )))
+        })?;
+        token
+            .parse::<f32>()
+            .map_err(|_| ctx.error(ParseErrorKind::BadNumber, token))
+    }
+
+    fn parse_optional_coordinate(
+        &self,
+        ctx: &LineContext,
+        token: Option<&&str>,
+        name: &str,
+        element: &str,
+        default: f32,
+    ) -> Result<f32, ParseError> {
+        match token {
+            Some(_) => self.parse_coordinate(ctx, token, name, element),
+            None => Ok(default),
+        }
+    }
+
+    fn check_component_count(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+        max: usize,
+        element: &str,
+    ) -> Result<(), ParseError> {
+        match split_line.get(max + 1) {
+            Some(token) => Err(ctx.error(
+                ParseErrorKind::TooManyComponents(element.to_string()),
+                token,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // v x y z [w], with w defaulting to 1.0
+    fn parse_vertex(&self, ctx: &LineContext, split_line: &[&str]) -> Result<Vertex, ParseError> {
+        let x = self.parse_coordinate(ctx, split_line.get(1), "x", "vertex")?;
+        let y = self.parse_coordinate(ctx, split_line.get(2), "y", "vertex")?;
+        let z = self.parse_coordinate(ctx, split_line.get(3), "z", "vertex")?;
+        let w = self.parse_optional_coordinate(ctx, split_line.get(4), "w", "vertex", 1.)?;
+        self.check_component_count(ctx, split_line, 4, "vertex")?;
+        Ok(Vertex::new(x, y, z, w))
+    }
+
+    // vt u [v [w]], with v and w defaulting to 0.0
+    fn parse_texture_coordinate(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+    ) -> Result<TextureCoordinate, ParseError> {
+        let element = "texture coordinate";
+        let u = self.parse_coordinate(ctx, split_line.get(1), "u", element)?;
+        let v = self.parse_optional_coordinate(ctx, split_line.get(2), "v", element, 0.)?;
+        let w = self.parse_optional_coordinate(ctx, split_line.get(3), "w", element, 0.)?;
+        self.check_component_count(ctx, split_line, 3, element)?;
+        Ok(TextureCoordinate::new(u, v, w))
+    }
+
+    // vn x y z
+    fn parse_normal(&self, ctx: &LineContext, split_line: &[&str]) -> Result<Normal, ParseError> {
+        let x = self.parse_coordinate(ctx, split_line.get(1), "x", "normal")?;
+        let y = self.parse_coordinate(ctx, split_line.get(2), "y", "normal")?;
+        let z = self.parse_coordinate(ctx, split_line.get(3), "z", "normal")?;
+        self.check_component_count(ctx, split_line, 3, "normal")?;
+        Ok(Normal::new(x, y, z))
+    }
+
+    // Resolves a 1-based (or negative, relative to the end) OBJ index to a
+    // zero-based one, given how many elements have been declared so far.
+    fn resolve_index(
+        &self,
+        ctx: &LineContext,
+        token: &str,
+        count: usize,
+    ) -> Result<usize, ParseError> {
+        let index = token
+            .parse::<i64>()
+            .map_err(|_| ctx.error(ParseErrorKind::BadNumber, token))?;
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            count as i64 + index
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(ctx.error(ParseErrorKind::IndexOutOfRange, token));
+        }
+        Ok(resolved as usize)
+    }
+
+    // Accepts v, v/vt, v//vn and v/vt/vn
+    fn parse_face_vertex(
+        &self,
+        ctx: &LineContext,
+        token: &str,
+        (vertex_count, texture_count, normal_count): (usize, usize, usize),
+    ) -> Result<FaceVertex, ParseError> {
+        let parts: Vec<&str> = token.split('/').collect();
+        let malformed = || ctx.error(ParseErrorKind::MalformedFace, token);
+        if parts.len() > 3 || parts[0].is_empty() {
+            return Err(malformed());
+        }
+        let vertex = self.resolve_index(ctx, parts[0], vertex_count)?;
+        let texture = match parts.get(1) {
+            Some(part) if !part.is_empty() => Some(self.resolve_index(ctx, part, texture_count)?),
+            Some(_) if parts.len() == 2 => return Err(malformed()),
+            _ => None,
+        };
+        let normal = match parts.get(2) {
+            Some(part) if !part.is_empty() => Some(self.resolve_index(ctx, part, normal_count)?),
+            Some(_) => return Err(malformed()),
+            None => None,
+        };
+        Ok(FaceVertex {
+            vertex,
+            texture,
+            normal,
         })
     }
+
+    fn parse_face(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+        counts: (usize, usize, usize),
+    ) -> Result<Vec<FaceVertex>, ParseError> {
+        if split_line.len() < 4 {
+            return Err(ctx.error_at_end(ParseErrorKind::MissingComponent(
+                "vertices in face, at least 3 needed".to_string(),
+            )));
+        }
+        split_line[1..]
+            .iter()
+            .map(|token| self.parse_face_vertex(ctx, token, counts))
+            .collect()
+    }
+
+    // How many v, vt and vn have been declared so far, to resolve indices
+    fn element_counts(&self, scene: &Scene) -> (usize, usize, usize) {
+        (
+            scene.vertices.len(),
+            scene.texture_coordinates.len(),
+            scene.normals.len(),
+        )
+    }
+
+    // l v1[/vt1] v2[/vt2]... and p v1 v2..., keeping only the vertex indices
+    fn parse_element(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+        counts: (usize, usize, usize),
+        min: usize,
+        element: &str,
+    ) -> Result<Vec<usize>, ParseError> {
+        if split_line.len() < min + 1 {
+            return Err(ctx.error_at_end(ParseErrorKind::MissingComponent(format!(
+                "vertices in {}, at least {} needed",
+                element, min
+            ))));
+        }
+        split_line[1..]
+            .iter()
+            .map(|token| Ok(self.parse_face_vertex(ctx, token, counts)?.vertex))
+            .collect()
+    }
+
+    // Fan triangulation around the first corner; fine for the convex
+    // polygons exporters emit for quads and n-gons.
+    fn triangulate(
+        &self,
+        face: &[FaceVertex],
+        smoothing_group: u32,
+        material: Option<usize>,
+        triangles: &mut Vec<Triangle>,
+    ) {
+        for i in 1..face.len() - 1 {
+            let corners = [face[0], face[i], face[i + 1]];
+            triangles.push(Triangle::new(corners, smoothing_group, material));
+        }
+    }
 }
@@ -0,0 +1,486 @@
+use super::{LineContext, LineType, ParseError, ParseState, Parser};
+use crate::scene::{FaceVertex, Normal, Scene, TextureCoordinate, Vertex};
+use std::{
+    io::{self, BufRead, Read},
+    ops::Range,
+    thread,
+};
+
+// Bytes of input handed to each worker per batch
+const CHUNK_SIZE: usize = 1 << 22;
+
+// What a worker made of one non-blank line of its chunk. `line` is 1-based
+// within the chunk and `text` is the line's byte range in it.
+enum Record {
+    Vertex(Vertex),
+    TextureCoordinate(TextureCoordinate),
+    Normal(Normal),
+    // Face whose raw indices still have to be resolved against the number of
+    // elements declared before it in the whole file
+    Face {
+        line: usize,
+        text: Range<usize>,
+        corners: Range<usize>,
+    },
+    // Stateful or unusual lines, replayed by the serial parser in file order
+    Serial {
+        line: usize,
+        text: Range<usize>,
+    },
+    Error(ParseError),
+}
+
+// Input read for one round of workers
+struct Batch {
+    // Length of the complete lines at the start of the buffer
+    complete: usize,
+    end_of_input: bool,
+    error: Option<io::Error>,
+}
+
+struct Chunk {
+    records: Vec<Record>,
+    // Raw v/vt/vn indices of every face corner, 0 standing for a missing one
+    corners: Vec<[i64; 3]>,
+    line_count: usize,
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "stream did not contain valid UTF-8",
+    )
+}
+
+// Splits `data` into about `count` pieces, each ending right after a newline
+fn split_chunks(data: &[u8], count: usize) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for i in 1..count {
+        let target = (data.len() * i / count).max(start);
+        let end = match data[target..].iter().position(|&b| b == b'\n') {
+            Some(offset) => target + offset + 1,
+            None => data.len(),
+        };
+        chunks.push(&data[start..end]);
+        start = end;
+    }
+    chunks.push(&data[start..]);
+    chunks
+}
+
+// Resolves an index the same way `Parser::resolve_index` does, without
+// building an error on failure.
+fn resolve(index: i64, count: usize) -> Option<usize> {
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+    if resolved < 0 || resolved >= count as i64 {
+        return None;
+    }
+    Some(resolved as usize)
+}
+
+fn resolve_optional(index: i64, count: usize) -> Option<Option<usize>> {
+    match index {
+        0 => Some(None),
+        _ => resolve(index, count).map(Some),
+    }
+}
+
+// Parses face corners in the forms the serial parser accepts, returning
+// false for anything it would reject so that it produces the error itself.
+fn parse_raw_face(split_line: &[&str], corners: &mut Vec<[i64; 3]>) -> bool {
+    if split_line.len() < 4 {
+        return false;
+    }
+    let start = corners.len();
+    for token in split_line[1..].iter() {
+        let mut parts = token.split('/');
+        let parse = |part: &str| part.parse::<i64>().ok().filter(|&i| i != 0);
+        let corner = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(v), None, _, _) => parse(v).map(|v| [v, 0, 0]),
+            (Some(v), Some(vt), None, _) => parse(v).zip(parse(vt)).map(|(v, vt)| [v, vt, 0]),
+            (Some(v), Some(""), Some(vn), None) => {
+                parse(v).zip(parse(vn)).map(|(v, vn)| [v, 0, vn])
+            }
+            (Some(v), Some(vt), Some(vn), None) => parse(v)
+                .zip(parse(vt))
+                .zip(parse(vn))
+                .map(|((v, vt), vn)| [v, vt, vn]),
+            _ => None,
+        };
+        match corner {
+            Some(corner) => corners.push(corner),
+            None => {
+                corners.truncate(start);
+                return false;
+            }
+        }
+    }
+    true
+}
+
+impl<R: BufRead + Sync> Parser<R> {
+    /// Same result as `generate_scene`, but the number crunching of each batch
+    /// of input is split between `threads` workers. Everything that depends on
+    /// what came before (indices, objects, materials...) is then stitched in
+    /// file order, so the scene and any error are identical to a serial parse.
+    pub fn generate_scene_parallel(self, threads: usize) -> Result<Scene, ParseError> {
+        self.generate_scene_in_chunks(threads, CHUNK_SIZE)
+    }
+
+    // `generate_scene_parallel` handing each worker `chunk_size` bytes per
+    // batch, small sizes letting tests cross many chunk boundaries.
+    fn generate_scene_in_chunks(
+        mut self,
+        threads: usize,
+        chunk_size: usize,
+    ) -> Result<Scene, ParseError> {
+        if threads <= 1 {
+            return self.generate_scene();
+        }
+        let mut scene = Scene::new();
+        let mut state = ParseState::new();
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut line_count: usize = 0;
+
+        loop {
+            let batch = self.read_batch(&mut buffer, threads * chunk_size, chunk_size);
+            let chunks = split_chunks(&buffer[..batch.complete], threads);
+            let parser = &self;
+            let parsed: Vec<Chunk> = thread::scope(|s| {
+                let workers: Vec<_> = chunks
+                    .iter()
+                    .map(|chunk| s.spawn(move || parser.parse_chunk(chunk)))
+                    .collect();
+                workers
+                    .into_iter()
+                    .map(|worker| worker.join().expect("parser worker panicked"))
+                    .collect()
+            });
+            for (chunk, bytes) in parsed.into_iter().zip(chunks.iter()) {
+                let lines = chunk.line_count;
+                self.stitch(chunk, bytes, line_count, &mut scene, &mut state)?;
+                line_count += lines;
+            }
+            if let Some(e) = batch.error {
+                return Err(ParseError::io(&self.path, line_count + 1, e));
+            }
+            if batch.end_of_input {
+                break;
+            }
+            buffer.drain(..batch.complete);
+        }
+        self.close_object(&mut scene);
+        Ok(scene)
+    }
+
+    // Tops `buffer` up to at least `size` bytes, up to the end of the input,
+    // reading at least `step` bytes at a time. A read error ends the input,
+    // leaving the complete lines before it to be parsed.
+    fn read_batch(&mut self, buffer: &mut Vec<u8>, size: usize, step: usize) -> Batch {
+        let complete_lines =
+            |buffer: &[u8]| buffer.iter().rposition(|&b| b == b'\n').map(|i| i + 1);
+        loop {
+            let wanted = size.saturating_sub(buffer.len()).max(step) as u64;
+            let read = match (&mut self.reader).take(wanted).read_to_end(buffer) {
+                Ok(read) => read,
+                Err(e) => {
+                    return Batch {
+                        complete: complete_lines(buffer).unwrap_or(0),
+                        end_of_input: true,
+                        error: Some(e),
+                    }
+                }
+            };
+            if read == 0 {
+                return Batch {
+                    complete: buffer.len(),
+                    end_of_input: true,
+                    error: None,
+                };
+            }
+            // A single line longer than a whole batch keeps us reading
+            if let Some(complete) = complete_lines(buffer).filter(|_| buffer.len() >= size) {
+                return Batch {
+                    complete,
+                    end_of_input: false,
+                    error: None,
+                };
+            }
+        }
+    }
+
+    fn parse_chunk(&self, bytes: &[u8]) -> Chunk {
+        let mut chunk = Chunk {
+            records: Vec::new(),
+            corners: Vec::new(),
+            line_count: 0,
+        };
+        let mut offset = 0;
+        for line_bytes in bytes.split_inclusive(|&b| b == b'\n') {
+            chunk.line_count += 1;
+            let text = offset..offset + line_bytes.len();
+            offset += line_bytes.len();
+            let line = match std::str::from_utf8(line_bytes) {
+                Ok(line) => line.trim_end(),
+                Err(_) => {
+                    let e = ParseError::io(&self.path, chunk.line_count, invalid_utf8());
+                    chunk.records.push(Record::Error(e));
+                    break;
+                }
+            };
+            let split_line: Vec<&str> = line.split_whitespace().collect();
+            if split_line.is_empty() {
+                continue;
+            }
+            let ctx = LineContext {
+                path: &self.path,
+                number: chunk.line_count,
+                line,
+            };
+            let record = match self.get_line_type(split_line[0]) {
+                LineType::Comment => continue,
+                LineType::Vertices => self.parse_vertex(&ctx, &split_line).map(Record::Vertex),
+                LineType::TextureCoordinates => self
+                    .parse_texture_coordinate(&ctx, &split_line)
+                    .map(Record::TextureCoordinate),
+                LineType::Normals => self.parse_normal(&ctx, &split_line).map(Record::Normal),
+                LineType::Faces => {
+                    let start = chunk.corners.len();
+                    let line = chunk.line_count;
+                    if parse_raw_face(&split_line, &mut chunk.corners) {
+                        let corners = start..chunk.corners.len();
+                        Ok(Record::Face {
+                            line,
+                            text,
+                            corners,
+                        })
+                    } else {
+                        Ok(Record::Serial { line, text })
+                    }
+                }
+                _ => Ok(Record::Serial {
+                    line: chunk.line_count,
+                    text,
+                }),
+            };
+            match record {
+                Ok(record) => chunk.records.push(record),
+                Err(e) => {
+                    chunk.records.push(Record::Error(e));
+                    break;
+                }
+            }
+        }
+        chunk
+    }
+
+    fn stitch(
+        &self,
+        chunk: Chunk,
+        bytes: &[u8],
+        first_line: usize,
+        scene: &mut Scene,
+        state: &mut ParseState,
+    ) -> Result<(), ParseError> {
+        let mut face: Vec<FaceVertex> = Vec::new();
+        for record in chunk.records {
+            match record {
+                Record::Vertex(vertex) => scene.vertices.push(vertex),
+                Record::TextureCoordinate(texture) => scene.texture_coordinates.push(texture),
+                Record::Normal(normal) => scene.normals.push(normal),
+                Record::Face {
+                    line,
+                    text,
+                    corners,
+                } => {
+                    let (vertex_count, texture_count, normal_count) = self.element_counts(scene);
+                    let corners_len = corners.len();
+                    face.clear();
+                    for &[v, vt, vn] in chunk.corners[corners].iter() {
+                        let corner = (
+                            resolve(v, vertex_count),
+                            resolve_optional(vt, texture_count),
+                            resolve_optional(vn, normal_count),
+                        );
+                        if let (Some(vertex), Some(texture), Some(normal)) = corner {
+                            face.push(FaceVertex {
+                                vertex,
+                                texture,
+                                normal,
+                            });
+                        } else {
+                            break;
+                        }
+                    }
+                    if face.len() == corners_len {
+                        self.add_face(&face, scene, state);
+                    } else {
+                        // Out of range, let the serial parser report it
+                        self.replay(&bytes[text], first_line + line, scene, state)?
+                    }
+                }
+                Record::Serial { line, text } => {
+                    self.replay(&bytes[text], first_line + line, scene, state)?
+                }
+                Record::Error(mut e) => {
+                    e.line += first_line;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn replay(
+        &self,
+        bytes: &[u8],
+        number: usize,
+        scene: &mut Scene,
+        state: &mut ParseState,
+    ) -> Result<(), ParseError> {
+        let line = std::str::from_utf8(bytes)
+            .map_err(|_| ParseError::io(&self.path, number, invalid_utf8()))?
+            .trim_end();
+        let split_line: Vec<&str> = line.split_whitespace().collect();
+        let ctx = LineContext {
+            path: &self.path,
+            number,
+            line,
+        };
+        self.parse_line(&ctx, &split_line, scene, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHUNK: usize = 64;
+
+    // A grid of quads with every directive the stitching has to replay in
+    // order, repeated so that it spreads over many chunks
+    fn obj(rows: usize) -> String {
+        let mut text = String::from("# grid\nv 0 0 0\nv 0 1 0\n");
+        for row in 1..=rows {
+            text += &format!("v {} 0 0\nv {} 1 0.5\nvt {} 0\nvn 0 0 1\n", row, row, row);
+            match row % 5 {
+                0 => text += &format!("o part {}\n", row),
+                1 => text += &format!("g left g{}\n", row),
+                2 => text += &format!("s {}\nusemtl m{}\n", row % 3, row % 4),
+                3 => text += "s off\n\ng\nvp 0.5\nzz vendor extension\n",
+                _ => {}
+            }
+            let (a, b) = (2 * row - 1, 2 * row);
+            if row % 2 == 0 {
+                text += &format!("f {} {} {} {}\n", a, a + 2, b + 2, b);
+            } else {
+                text += "f -4/-1/-1 -2/-1/-1 -1//-1 -3//-1\n";
+            }
+            if row % 7 == 0 {
+                text += &format!("l {} -1 -2\np -1 {}\n", a, b);
+            }
+        }
+        text
+    }
+
+    fn parse(text: &str, threads: usize) -> Result<Scene, ParseError> {
+        let parser = Parser::from_reader(text.as_bytes(), "<test>");
+        match threads {
+            1 => parser.generate_scene(),
+            _ => parser.generate_scene_in_chunks(threads, CHUNK),
+        }
+    }
+
+    fn summary(scene: &Scene) -> Vec<String> {
+        let mut lines = Vec::new();
+        for v in scene.vertices.iter() {
+            lines.push(format!("v {} {} {} {}", v.x, v.y, v.z, v.w));
+        }
+        for vt in scene.texture_coordinates.iter() {
+            lines.push(format!("vt {} {} {}", vt.u, vt.v, vt.w));
+        }
+        for vn in scene.normals.iter() {
+            lines.push(format!("vn {} {} {}", vn.x, vn.y, vn.z));
+        }
+        for t in scene.triangles.iter() {
+            let corners = t.corners.map(|c| (c.vertex, c.texture, c.normal));
+            lines.push(format!(
+                "f {:?} s {} m {:?}",
+                corners, t.smoothing_group, t.material
+            ));
+        }
+        for object in scene.objects.iter() {
+            lines.push(format!("o {:?} {:?}", object.name, object.triangles));
+            for group in object.groups.iter() {
+                lines.push(format!("g {:?} {:?}", group.names, group.triangles));
+            }
+        }
+        for material in scene.materials.iter() {
+            lines.push(format!("usemtl {}", material.name));
+        }
+        lines.push(format!("l {:?}", scene.lines));
+        lines.push(format!("p {:?}", scene.points));
+        lines
+    }
+
+    fn error(text: &str, threads: usize) -> String {
+        let e = parse(text, threads).err().expect("the parse should fail");
+        format!(
+            "{} {} {} {:?} {}",
+            e.path, e.line, e.column, e.token, e.kind
+        )
+    }
+
+    #[test]
+    fn parallel_parse_gives_the_serial_scene() {
+        let text = obj(200);
+        assert!(text.len() > 50 * CHUNK);
+        let serial = summary(&parse(&text, 1).unwrap());
+        assert!(serial.iter().any(|line| line.starts_with("o \"part 200\"")));
+        for threads in [2, 3, 8] {
+            assert_eq!(summary(&parse(&text, threads).unwrap()), serial);
+        }
+    }
+
+    #[test]
+    fn parallel_parse_handles_lines_longer_than_a_chunk() {
+        let mut text = obj(20);
+        text += &format!("o {}\nf 1 2 3\n", "long name ".repeat(3 * CHUNK));
+        text += &obj(20);
+        let serial = summary(&parse(&text, 1).unwrap());
+        assert_eq!(summary(&parse(&text, 4).unwrap()), serial);
+    }
+
+    #[test]
+    fn parallel_parse_reports_the_serial_errors() {
+        let lines: Vec<String> = obj(150).lines().map(str::to_string).collect();
+        let broken = [
+            (400, "v 1 2 z"),
+            (410, "f 1/1/1 2/x/1 3/1/1"),
+            (420, "f 1 2 99999"),
+            (430, "f -1 -2 -9999"),
+            (440, "vn 0 0"),
+            (450, "s twelve"),
+            (460, "f 1//1 2/"),
+        ];
+        for (number, line) in broken {
+            let mut text = lines[..number - 1].join("\n");
+            text += &format!("\n{}\n", line);
+            text += &lines[number - 1..].join("\n");
+            let serial = error(&text, 1);
+            assert!(
+                serial.starts_with(&format!("<test> {} ", number)),
+                "{}",
+                serial
+            );
+            for threads in [2, 5] {
+                assert_eq!(error(&text, threads), serial);
+            }
+        }
+    }
+}
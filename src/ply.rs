@@ -0,0 +1,712 @@
+use crate::parser::{LineContext, ParseError, ParseErrorKind};
+use crate::scene::{
+    FaceVertex, Normal, Object, Scene, TextureCoordinate, Triangle, Vertex, VertexProperty,
+};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader},
+    ops::Range,
+};
+
+/// PLY parser for ASCII and binary files of either endianness. The `vertex`
+/// element gives positions, normals, texture coordinates and RGBA colors,
+/// and every other scalar property of it is kept in
+/// `Scene::vertex_properties`. `face` and `edge` elements give triangles and
+/// lines, and other elements are skipped.
+pub struct PlyParser<R: BufRead> {
+    path: String,
+    reader: R,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum ScalarType {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+#[derive(Clone, Copy)]
+enum PropertyType {
+    Scalar(ScalarType),
+    // Count type, then item type
+    List(ScalarType, ScalarType),
+}
+
+struct Property {
+    name: String,
+    kind: PropertyType,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+// Where the value of a property ends up
+#[derive(Clone, Copy)]
+enum Target {
+    Position(usize),
+    Normal(usize),
+    Texture(usize),
+    Color(usize),
+    Custom(usize),
+    Face,
+    Edge(usize),
+    Skip,
+}
+
+impl ScalarType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => ScalarType::Int8,
+            "uchar" | "uint8" => ScalarType::UInt8,
+            "short" | "int16" => ScalarType::Int16,
+            "ushort" | "uint16" => ScalarType::UInt16,
+            "int" | "int32" => ScalarType::Int32,
+            "uint" | "uint32" => ScalarType::UInt32,
+            "float" | "float32" => ScalarType::Float32,
+            "double" | "float64" => ScalarType::Float64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => 1,
+            ScalarType::Int16 | ScalarType::UInt16 => 2,
+            ScalarType::Int32 | ScalarType::UInt32 | ScalarType::Float32 => 4,
+            ScalarType::Float64 => 8,
+        }
+    }
+
+    // Colors stored as integers span the whole unsigned range
+    fn color_scale(self) -> f64 {
+        match self {
+            ScalarType::Int8 | ScalarType::UInt8 => u8::MAX as f64,
+            ScalarType::Int16 | ScalarType::UInt16 => u16::MAX as f64,
+            ScalarType::Int32 | ScalarType::UInt32 => u32::MAX as f64,
+            ScalarType::Float32 | ScalarType::Float64 => 1.,
+        }
+    }
+
+    // `bytes` holds the value in little endian order
+    fn decode(self, b: &[u8]) -> f64 {
+        match self {
+            ScalarType::Int8 => b[0] as i8 as f64,
+            ScalarType::UInt8 => b[0] as f64,
+            ScalarType::Int16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            ScalarType::UInt16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            ScalarType::Int32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::UInt32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::Float32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            ScalarType::Float64 => f64::from_le_bytes(b[..8].try_into().unwrap()),
+        }
+    }
+}
+
+impl Element {
+    // Maps the properties of the elements the scene is built from
+    fn targets(&self, scene: &mut Scene) -> Vec<Target> {
+        self.properties
+            .iter()
+            .map(|property| {
+                let name = property.name.as_str();
+                match (self.name.as_str(), property.kind) {
+                    ("vertex", PropertyType::Scalar(_)) => match name {
+                        "x" => Target::Position(0),
+                        "y" => Target::Position(1),
+                        "z" => Target::Position(2),
+                        "nx" => Target::Normal(0),
+                        "ny" => Target::Normal(1),
+                        "nz" => Target::Normal(2),
+                        "u" | "s" | "texture_u" | "texture_s" => Target::Texture(0),
+                        "v" | "t" | "texture_v" | "texture_t" => Target::Texture(1),
+                        "red" | "diffuse_red" => Target::Color(0),
+                        "green" | "diffuse_green" => Target::Color(1),
+                        "blue" | "diffuse_blue" => Target::Color(2),
+                        "alpha" => Target::Color(3),
+                        _ => {
+                            scene.vertex_properties.push(VertexProperty {
+                                name: name.to_string(),
+                                values: Vec::with_capacity(self.count),
+                            });
+                            Target::Custom(scene.vertex_properties.len() - 1)
+                        }
+                    },
+                    ("face", PropertyType::List(..))
+                        if name == "vertex_indices" || name == "vertex_index" =>
+                    {
+                        Target::Face
+                    }
+                    ("edge", PropertyType::Scalar(_)) if name == "vertex1" => Target::Edge(0),
+                    ("edge", PropertyType::Scalar(_)) if name == "vertex2" => Target::Edge(1),
+                    _ => Target::Skip,
+                }
+            })
+            .collect()
+    }
+}
+
+// The data after the header, read one scalar at a time
+struct Body<'a, R: BufRead> {
+    path: &'a str,
+    reader: R,
+    format: Format,
+    // ASCII only: the line being read, its number and the last token taken
+    line: String,
+    number: usize,
+    token: Range<usize>,
+    // The element being read and its index, which is all binary errors can
+    // point at
+    element: String,
+    item: usize,
+}
+
+impl<'a, R: BufRead> Body<'a, R> {
+    fn scalar(&mut self, kind: ScalarType) -> Result<f64, ParseError> {
+        if self.format != Format::Ascii {
+            let mut bytes = [0; 8];
+            let bytes = &mut bytes[..kind.size()];
+            self.reader.read_exact(bytes).map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => self.error(ParseErrorKind::MissingComponent(
+                    "values, the file ended early".to_string(),
+                )),
+                _ => self.error(ParseErrorKind::Io(e)),
+            })?;
+            if self.format == Format::BinaryBigEndian {
+                bytes.reverse();
+            }
+            return Ok(kind.decode(bytes));
+        }
+        self.next_token()?;
+        let token = &self.line[self.token.clone()];
+        token.parse::<f64>().map_err(|_| {
+            let ctx = self.context();
+            ctx.error(ParseErrorKind::BadNumber, &ctx.line[self.token.clone()])
+        })
+    }
+
+    // Values may be spread over lines however the writer liked
+    fn next_token(&mut self) -> Result<(), ParseError> {
+        loop {
+            let rest = &self.line[self.token.end..];
+            let start = self.token.end + (rest.len() - rest.trim_start().len());
+            let rest = &self.line[start..];
+            if !rest.is_empty() {
+                let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                self.token = start..start + len;
+                return Ok(());
+            }
+            self.line.clear();
+            self.number += 1;
+            self.token = 0..0;
+            let read = self
+                .reader
+                .read_line(&mut self.line)
+                .map_err(|e| ParseError::io(self.path, self.number, e))?;
+            if read == 0 {
+                self.line.clear();
+                return Err(self.error(ParseErrorKind::MissingComponent(
+                    "values, the file ended early".to_string(),
+                )));
+            }
+        }
+    }
+
+    fn context(&self) -> LineContext<'_> {
+        LineContext {
+            path: self.path,
+            number: self.number,
+            line: &self.line,
+        }
+    }
+
+    // Points at the last value read in ASCII files, and at the element being
+    // read in binary ones, e.g. face[12]
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        if self.format == Format::Ascii {
+            let ctx = self.context();
+            return ctx.error(kind, &ctx.line[self.token.clone()]);
+        }
+        ParseError {
+            path: self.path.to_string(),
+            line: 0,
+            column: 0,
+            token: format!("{}[{}]", self.element, self.item),
+            kind,
+        }
+    }
+
+    fn index(&mut self, kind: ScalarType, count: usize) -> Result<usize, ParseError> {
+        let index = self.scalar(kind)?;
+        if index < 0. || index >= count as f64 || index.fract() != 0. {
+            return Err(self.error(ParseErrorKind::IndexOutOfRange));
+        }
+        Ok(index as usize)
+    }
+}
+
+impl PlyParser<BufReader<File>> {
+    pub fn new(filename: &str) -> Result<Self, ParseError> {
+        let file = File::open(filename).map_err(|e| ParseError::io(filename, 0, e))?;
+        Ok(Self::from_reader(BufReader::new(file), filename))
+    }
+}
+
+impl<R: BufRead> PlyParser<R> {
+    /// `path` labels errors, e.g. "<stdin>".
+    pub fn from_reader(reader: R, path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            reader,
+        }
+    }
+
+    pub fn generate_scene(mut self) -> Result<Scene, ParseError> {
+        let (format, elements, number) = self.parse_header()?;
+        let vertex_count = elements
+            .iter()
+            .find(|element| element.name == "vertex")
+            .map_or(0, |element| element.count);
+        let mut scene = Scene::new();
+        let mut body = Body {
+            path: &self.path,
+            reader: self.reader,
+            format,
+            line: String::new(),
+            number,
+            token: 0..0,
+            element: String::new(),
+            item: 0,
+        };
+        for element in elements.iter() {
+            body.element.clone_from(&element.name);
+            let targets = element.targets(&mut scene);
+            match element.name.as_str() {
+                "vertex" => read_vertices(&mut body, element, &targets, &mut scene)?,
+                _ => read_elements(&mut body, element, &targets, vertex_count, &mut scene)?,
+            }
+        }
+
+        // Corners take the normal and texture coordinates of their vertex
+        let normals = !scene.normals.is_empty();
+        let textures = !scene.texture_coordinates.is_empty();
+        for corner in scene
+            .triangles
+            .iter_mut()
+            .flat_map(|t| t.corners.iter_mut())
+        {
+            corner.normal = normals.then_some(corner.vertex);
+            corner.texture = textures.then_some(corner.vertex);
+        }
+        if !scene.triangles.is_empty() {
+            let mut object = Object::new("", 0);
+            object.triangles.end = scene.triangles.len();
+            scene.objects.push(object);
+        }
+        Ok(scene)
+    }
+
+    // ply / format ascii|binary_little_endian|binary_big_endian 1.0 /
+    // element name count / property type name /
+    // property list count_type item_type name / end_header, with comment and
+    // obj_info lines anywhere. Returns the number of the last header line.
+    fn parse_header(&mut self) -> Result<(Format, Vec<Element>, usize), ParseError> {
+        let mut format = None;
+        let mut elements: Vec<Element> = Vec::new();
+        let mut line = String::new();
+        let mut number: usize = 0;
+
+        loop {
+            line.clear();
+            number += 1;
+            let read = self
+                .reader
+                .read_line(&mut line)
+                .map_err(|e| ParseError::io(&self.path, number, e))?;
+            if read == 0 {
+                return Err(ParseError {
+                    path: self.path.clone(),
+                    line: 0,
+                    column: 0,
+                    token: String::new(),
+                    kind: ParseErrorKind::MissingComponent("end_header".to_string()),
+                });
+            }
+            let line = line.trim_end();
+            let split_line: Vec<&str> = line.split_whitespace().collect();
+            let ctx = LineContext {
+                path: &self.path,
+                number,
+                line,
+            };
+            let missing =
+                |what: &str| ctx.error_at_end(ParseErrorKind::MissingComponent(what.to_string()));
+            if number == 1 {
+                if line != "ply" {
+                    return Err(missing("ply magic number"));
+                }
+                continue;
+            }
+            let Some(&keyword) = split_line.first() else {
+                continue;
+            };
+            match keyword {
+                "comment" | "obj_info" => {}
+                "format" => {
+                    let token = split_line.get(1).ok_or_else(|| missing("format"))?;
+                    format = Some(match *token {
+                        "ascii" => Format::Ascii,
+                        "binary_little_endian" => Format::BinaryLittleEndian,
+                        "binary_big_endian" => Format::BinaryBigEndian,
+                        _ => {
+                            let kind = ParseErrorKind::Unsupported("format".to_string());
+                            return Err(ctx.error(kind, token));
+                        }
+                    });
+                }
+                "element" => {
+                    let name = split_line.get(1).ok_or_else(|| missing("element name"))?;
+                    let count = split_line.get(2).ok_or_else(|| missing("element count"))?;
+                    let count = count
+                        .parse::<usize>()
+                        .map_err(|_| ctx.error(ParseErrorKind::BadNumber, count))?;
+                    elements.push(Element {
+                        name: name.to_string(),
+                        count,
+                        properties: Vec::new(),
+                    });
+                }
+                "property" => {
+                    let Some(element) = elements.last_mut() else {
+                        let kind = ParseErrorKind::MissingComponent("element".to_string());
+                        return Err(ctx.error(kind, keyword));
+                    };
+                    let scalar = |i: usize| {
+                        let token = split_line.get(i).ok_or_else(|| missing("property type"))?;
+                        ScalarType::parse(token).ok_or_else(|| {
+                            ctx.error(
+                                ParseErrorKind::Unsupported("property type".to_string()),
+                                token,
+                            )
+                        })
+                    };
+                    let (kind, name) = match split_line.get(1) {
+                        Some(&"list") => (PropertyType::List(scalar(2)?, scalar(3)?), 4),
+                        _ => (PropertyType::Scalar(scalar(1)?), 2),
+                    };
+                    let name = split_line
+                        .get(name)
+                        .ok_or_else(|| missing("property name"))?;
+                    element.properties.push(Property {
+                        name: name.to_string(),
+                        kind,
+                    });
+                }
+                "end_header" => break,
+                _ => return Err(ctx.error(ParseErrorKind::UnknownDirective, keyword)),
+            }
+        }
+        let format = format.ok_or_else(|| ParseError {
+            path: self.path.clone(),
+            line: number,
+            column: 0,
+            token: String::new(),
+            kind: ParseErrorKind::MissingComponent("format".to_string()),
+        })?;
+        Ok((format, elements, number))
+    }
+}
+
+fn read_vertices<R: BufRead>(
+    body: &mut Body<R>,
+    element: &Element,
+    targets: &[Target],
+    scene: &mut Scene,
+) -> Result<(), ParseError> {
+    let has = |target: fn(&Target) -> bool| targets.iter().any(target);
+    let normals = has(|t| matches!(t, Target::Normal(_)));
+    let textures = has(|t| matches!(t, Target::Texture(_)));
+    let colors = has(|t| matches!(t, Target::Color(_)));
+    for item in 0..element.count {
+        body.item = item;
+        let mut position = [0.; 3];
+        let mut normal = [0.; 3];
+        let mut texture = [0.; 2];
+        // Alpha stays opaque when only RGB is given
+        let mut color = [0., 0., 0., 1.];
+        for (property, &target) in element.properties.iter().zip(targets.iter()) {
+            let PropertyType::Scalar(kind) = property.kind else {
+                skip(body, property.kind)?;
+                continue;
+            };
+            let value = body.scalar(kind)?;
+            match target {
+                Target::Position(i) => position[i] = value as f32,
+                Target::Normal(i) => normal[i] = value as f32,
+                Target::Texture(i) => texture[i] = value as f32,
+                Target::Color(i) => color[i] = (value / kind.color_scale()) as f32,
+                Target::Custom(i) => scene.vertex_properties[i].values.push(value as f32),
+                _ => {}
+            }
+        }
+        let [x, y, z] = position;
+        scene.vertices.push(Vertex::new(x, y, z, 1.));
+        if normals {
+            let [x, y, z] = normal;
+            scene.normals.push(Normal::new(x, y, z));
+        }
+        if textures {
+            let [u, v] = texture;
+            scene
+                .texture_coordinates
+                .push(TextureCoordinate::new(u, v, 0.));
+        }
+        if colors {
+            scene.vertex_colors.push(color);
+        }
+    }
+    Ok(())
+}
+
+// Faces and edges, as indices into the vertex element. Faces are fanned into
+// triangles and smoothed, scans being sampled surfaces.
+fn read_elements<R: BufRead>(
+    body: &mut Body<R>,
+    element: &Element,
+    targets: &[Target],
+    vertex_count: usize,
+    scene: &mut Scene,
+) -> Result<(), ParseError> {
+    let edges = targets.iter().any(|t| matches!(t, Target::Edge(_)));
+    let mut face: Vec<usize> = Vec::new();
+    for item in 0..element.count {
+        body.item = item;
+        let mut edge = [0; 2];
+        for (property, &target) in element.properties.iter().zip(targets.iter()) {
+            match (property.kind, target) {
+                (PropertyType::List(count, item), Target::Face) => {
+                    let count = body.index(count, usize::MAX)?;
+                    face.clear();
+                    for _ in 0..count {
+                        face.push(body.index(item, vertex_count)?);
+                    }
+                    if count < 3 {
+                        return Err(body.error(ParseErrorKind::MissingComponent(
+                            "vertices in face, at least 3 needed".to_string(),
+                        )));
+                    }
+                    for i in 1..face.len() - 1 {
+                        let corners = [face[0], face[i], face[i + 1]].map(|vertex| FaceVertex {
+                            vertex,
+                            texture: None,
+                            normal: None,
+                        });
+                        scene.triangles.push(Triangle::new(corners, 1, None));
+                    }
+                }
+                (PropertyType::Scalar(kind), Target::Edge(i)) => {
+                    edge[i] = body.index(kind, vertex_count)?
+                }
+                (kind, _) => skip(body, kind)?,
+            }
+        }
+        if edges {
+            scene.lines.push(edge);
+        }
+    }
+    Ok(())
+}
+
+fn skip<R: BufRead>(body: &mut Body<R>, kind: PropertyType) -> Result<(), ParseError> {
+    match kind {
+        PropertyType::Scalar(kind) => {
+            body.scalar(kind)?;
+        }
+        PropertyType::List(count, item) => {
+            for _ in 0..body.index(count, usize::MAX)? {
+                body.scalar(item)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A value as the file stores it, in the type its property declares
+    #[derive(Clone, Copy)]
+    enum Value {
+        F32(f32),
+        U8(u8),
+        U16(u16),
+        I32(i32),
+    }
+
+    const HEADER: &str = "\
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+property float confidence
+property ushort id
+element face 2
+property list uchar int vertex_indices
+element edge 1
+property int vertex1
+property int vertex2
+end_header
+";
+
+    fn vertex(position: [f32; 3], color: [u8; 3], confidence: f32, id: u16) -> Vec<Value> {
+        let mut values: Vec<Value> = position.into_iter().map(Value::F32).collect();
+        values.extend(color.map(Value::U8));
+        values.extend([Value::F32(confidence), Value::U16(id)]);
+        values
+    }
+
+    // Vertex counts are declared as uchar
+    fn face(indices: &[i32]) -> Vec<Value> {
+        let mut values = vec![Value::U8(indices.len() as u8)];
+        values.extend(indices.iter().map(|&i| Value::I32(i)));
+        values
+    }
+
+    // One element per line
+    fn body() -> Vec<Vec<Value>> {
+        vec![
+            vertex([0., 0., 0.], [255, 0, 0], 0.5, 1),
+            vertex([1.5, 0., 0.], [0, 255, 0], 0.25, 300),
+            vertex([1.5, -2.25, 0.], [0, 0, 255], 1., 2),
+            vertex([0., -2.25, 1.], [51, 102, 153], 0.75, 65535),
+            face(&[0, 1, 2, 3]),
+            face(&[0, 2, 3]),
+            vec![Value::I32(1), Value::I32(3)],
+        ]
+    }
+
+    fn encode(format: &str, body: &[Vec<Value>]) -> Vec<u8> {
+        let mut bytes =
+            format!("ply\nformat {} 1.0\ncomment test\n{}", format, HEADER).into_bytes();
+        for line in body {
+            let mut text: Vec<String> = Vec::new();
+            for &value in line.iter() {
+                let (mut le, string) = match value {
+                    Value::F32(v) => (v.to_le_bytes().to_vec(), v.to_string()),
+                    Value::U8(v) => (vec![v], v.to_string()),
+                    Value::U16(v) => (v.to_le_bytes().to_vec(), v.to_string()),
+                    Value::I32(v) => (v.to_le_bytes().to_vec(), v.to_string()),
+                };
+                match format {
+                    "ascii" => text.push(string),
+                    "binary_big_endian" => {
+                        le.reverse();
+                        bytes.extend(le)
+                    }
+                    _ => bytes.extend(le),
+                }
+            }
+            if format == "ascii" {
+                bytes.extend(format!("{}\n", text.join(" ")).into_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Scene, ParseError> {
+        PlyParser::from_reader(bytes, "<test>").generate_scene()
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        let e = parse(bytes).err().expect("the parse should fail");
+        format!("{}:{} {} '{}'", e.line, e.column, e.kind, e.token)
+    }
+
+    const FORMATS: [&str; 3] = ["ascii", "binary_little_endian", "binary_big_endian"];
+
+    #[test]
+    fn every_encoding_gives_the_same_scene() {
+        for format in FORMATS {
+            let scene = parse(&encode(format, &body())).unwrap();
+            let positions: Vec<[f32; 3]> = scene.vertices.iter().map(|v| [v.x, v.y, v.z]).collect();
+            assert_eq!(
+                positions,
+                [
+                    [0., 0., 0.],
+                    [1.5, 0., 0.],
+                    [1.5, -2.25, 0.],
+                    [0., -2.25, 1.]
+                ],
+                "{}",
+                format
+            );
+            assert_eq!(scene.vertex_colors[0], [1., 0., 0., 1.]);
+            assert_eq!(scene.vertex_colors[3], [0.2, 0.4, 0.6, 1.]);
+            let properties: Vec<(&str, &[f32])> = scene
+                .vertex_properties
+                .iter()
+                .map(|p| (p.name.as_str(), p.values.as_slice()))
+                .collect();
+            assert_eq!(
+                properties,
+                [
+                    ("confidence", &[0.5, 0.25, 1., 0.75][..]),
+                    ("id", &[1., 300., 2., 65535.][..])
+                ]
+            );
+            let triangles: Vec<[usize; 3]> = scene
+                .triangles
+                .iter()
+                .map(|t| t.corners.map(|c| c.vertex))
+                .collect();
+            assert_eq!(triangles, [[0, 1, 2], [0, 2, 3], [0, 2, 3]]);
+            assert_eq!(scene.lines, [[1, 3]]);
+            assert!(scene.normals.is_empty());
+        }
+    }
+
+    #[test]
+    fn errors_point_at_the_value_or_element() {
+        let mut bad_index = body();
+        bad_index[5][2] = Value::I32(4);
+        assert_eq!(
+            error(&encode("ascii", &bad_index)),
+            "24:5 index out of range '4'"
+        );
+        for format in &FORMATS[1..] {
+            assert_eq!(
+                error(&encode(format, &bad_index)),
+                "0:0 index out of range 'face[1]'"
+            );
+        }
+
+        let mut bytes = encode("binary_little_endian", &body()[..3]);
+        bytes.extend([0; 5]);
+        assert_eq!(
+            error(&bytes),
+            "0:0 missing values, the file ended early 'vertex[3]'"
+        );
+
+        let text = String::from_utf8(encode("ascii", &body())).unwrap();
+        let text = text.replace("0.25 300", "0.25 three");
+        assert_eq!(error(text.as_bytes()), "20:22 invalid number 'three'");
+    }
+}
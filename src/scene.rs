@@ -1,3 +1,7 @@
+use crate::tuple::{vector, Vector};
+use std::{ops::Range, path::PathBuf};
+
+#[derive(Clone, Copy)]
 pub struct Vertex {
     pub x: f32,
     pub y: f32,
@@ -5,8 +9,268 @@ pub struct Vertex {
     pub w: f32,
 }
 
+#[derive(Clone, Copy)]
+pub struct TextureCoordinate {
+    pub u: f32,
+    pub v: f32,
+    pub w: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct Normal {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+#[derive(Clone, Copy)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+/// Surface description from a `newmtl` block of an MTL library. Texture maps
+/// are already resolved relative to the OBJ file's directory.
+#[derive(Clone)]
+pub struct Material {
+    pub name: String,
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+    pub emissive: Color,
+    pub shininess: f32,
+    pub optical_density: f32,
+    pub dissolve: f32,
+    pub illumination: u32,
+    pub diffuse_map: Option<PathBuf>,
+    pub specular_map: Option<PathBuf>,
+    pub bump_map: Option<PathBuf>,
+    pub dissolve_map: Option<PathBuf>,
+}
+
+/// One corner of a face, as zero-based indices into the scene arrays.
+#[derive(Clone, Copy)]
+pub struct FaceVertex {
+    pub vertex: usize,
+    pub texture: Option<usize>,
+    pub normal: Option<usize>,
+}
+
+/// `smoothing_group` comes from the last `s` line, 0 meaning smoothing is off,
+/// and `material` from the last `usemtl` line, as an index into
+/// `Scene::materials`.
+#[derive(Clone, Copy)]
+pub struct Triangle {
+    pub corners: [FaceVertex; 3],
+    pub smoothing_group: u32,
+    pub material: Option<usize>,
+}
+
+/// Faces declared after a `g` line, as a range into `Scene::triangles`. A face
+/// may belong to several groups at once, hence the list of names.
+#[derive(Clone)]
+pub struct Group {
+    pub names: Vec<String>,
+    pub triangles: Range<usize>,
+}
+
+/// Faces declared after an `o` line, as a range into `Scene::triangles`. Its
+/// groups cover sub-ranges of `triangles`.
+#[derive(Clone)]
+pub struct Object {
+    pub name: String,
+    pub triangles: Range<usize>,
+    pub groups: Vec<Group>,
+}
+
+/// Node of the hierarchy a file was authored with. `matrix` is its column-major
+/// transform relative to `parent`, an index into `Scene::nodes`. Vertices are
+/// already in world space: `object` only tells which of `Scene::objects` the
+/// node's mesh became.
+#[derive(Clone)]
+pub struct Node {
+    pub name: String,
+    pub parent: Option<usize>,
+    pub matrix: [f32; 16],
+    pub object: Option<usize>,
+}
+
+/// Per-vertex scalar that has no meaning of its own to the viewer, such as a
+/// scanner's confidence, with one value per vertex.
+#[derive(Clone)]
+pub struct VertexProperty {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+/// Axis-aligned box enclosing every vertex of a scene.
+#[derive(Clone, Copy)]
+pub struct BoundingBox {
+    pub min: Vector,
+    pub max: Vector,
+}
+
+/// `lines` holds the segments of every `l` polyline and `points` the vertices
+/// of `p` elements, both as zero-based indices into `vertices`.
+/// `vertex_colors` is either empty or holds an RGBA color per vertex, and
+/// each of `vertex_properties` has a value per vertex. `nodes` is empty unless
+/// the format has a node hierarchy, and lists parents before their children.
+#[derive(Clone)]
 pub struct Scene {
     pub vertices: Vec<Vertex>,
+    pub vertex_colors: Vec<[f32; 4]>,
+    pub vertex_properties: Vec<VertexProperty>,
+    pub texture_coordinates: Vec<TextureCoordinate>,
+    pub normals: Vec<Normal>,
+    pub triangles: Vec<Triangle>,
+    pub lines: Vec<[usize; 2]>,
+    pub points: Vec<usize>,
+    pub objects: Vec<Object>,
+    pub nodes: Vec<Node>,
+    pub materials: Vec<Material>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self {
+            vertices: Vec::new(),
+            vertex_colors: Vec::new(),
+            vertex_properties: Vec::new(),
+            texture_coordinates: Vec::new(),
+            normals: Vec::new(),
+            triangles: Vec::new(),
+            lines: Vec::new(),
+            points: Vec::new(),
+            objects: Vec::new(),
+            nodes: Vec::new(),
+            materials: Vec::new(),
+        }
+    }
+
+    pub fn material_index(&self, name: &str) -> Option<usize> {
+        self.materials.iter().position(|m| m.name == name)
+    }
+
+    /// None when there are no vertices at all.
+    pub fn bounding_box(&self) -> Option<BoundingBox> {
+        let first = self.vertices.first()?;
+        let start = vector(first.x, first.y, first.z);
+        Some(
+            self.vertices
+                .iter()
+                .fold(BoundingBox::new(start, start), |bounds, v| {
+                    bounds.extend(vector(v.x, v.y, v.z))
+                }),
+        )
+    }
+
+    /// Average position of the vertices, None when there are none.
+    pub fn centroid(&self) -> Option<Vector> {
+        if self.vertices.is_empty() {
+            return None;
+        }
+        let sum = self
+            .vertices
+            .iter()
+            .fold(vector(0., 0., 0.), |sum, v| sum + vector(v.x, v.y, v.z));
+        Some(sum / self.vertices.len() as f32)
+    }
+
+    /// Adds a material, replacing any material with the same name, and
+    /// returns its index.
+    pub fn add_material(&mut self, material: Material) -> usize {
+        match self.material_index(&material.name) {
+            Some(index) => {
+                self.materials[index] = material;
+                index
+            }
+            None => {
+                self.materials.push(material);
+                self.materials.len() - 1
+            }
+        }
+    }
+}
+
+impl BoundingBox {
+    pub fn new(min: Vector, max: Vector) -> Self {
+        Self { min, max }
+    }
+
+    pub fn extend(self, p: Vector) -> Self {
+        Self {
+            min: vector(
+                self.min.x.min(p.x),
+                self.min.y.min(p.y),
+                self.min.z.min(p.z),
+            ),
+            max: vector(
+                self.max.x.max(p.x),
+                self.max.y.max(p.y),
+                self.max.z.max(p.z),
+            ),
+        }
+    }
+
+    pub fn center(&self) -> Vector {
+        (self.min + self.max) / 2.
+    }
+
+    pub fn size(&self) -> Vector {
+        self.max - self.min
+    }
+}
+
+impl Group {
+    pub fn new(names: Vec<String>, start: usize) -> Self {
+        Self {
+            names,
+            triangles: start..start,
+        }
+    }
+}
+
+impl Object {
+    pub fn new(name: &str, start: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            triangles: start..start,
+            groups: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+}
+
+impl Color {
+    pub fn new(r: f32, g: f32, b: f32) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl Material {
+    /// A material with the MTL defaults, used as well for `usemtl` names
+    /// missing from every library.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ambient: Color::new(0.2, 0.2, 0.2),
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(1., 1., 1.),
+            emissive: Color::new(0., 0., 0.),
+            shininess: 32.,
+            optical_density: 1.,
+            dissolve: 1.,
+            illumination: 2,
+            diffuse_map: None,
+            specular_map: None,
+            bump_map: None,
+            dissolve_map: None,
+        }
+    }
 }
 
 impl Vertex {
@@ -14,3 +278,25 @@ impl Vertex {
         Self { x, y, z, w }
     }
 }
+
+impl TextureCoordinate {
+    pub fn new(u: f32, v: f32, w: f32) -> Self {
+        Self { u, v, w }
+    }
+}
+
+impl Normal {
+    pub fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+}
+
+impl Triangle {
+    pub fn new(corners: [FaceVertex; 3], smoothing_group: u32, material: Option<usize>) -> Self {
+        Self {
+            corners,
+            smoothing_group,
+            material,
+        }
+    }
+}
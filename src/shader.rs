@@ -1,4 +1,4 @@
-use crate::{matrices::Matrix, tuple::Tuple};
+use crate::{matrices::Matrix, scene::Color, tuple::Tuple};
 use gl::types::{GLchar, GLint};
 use std::{
     ffi::{CStr, CString},
@@ -149,4 +149,15 @@ impl Shader {
             );
         }
     }
+
+    pub fn set_color(&self, name: &CStr, c: Color) {
+        unsafe {
+            gl::Uniform3f(
+                gl::GetUniformLocation(self.id, name.as_ptr()),
+                c.r,
+                c.g,
+                c.b,
+            );
+        }
+    }
 }
@@ -4,7 +4,14 @@ out vec4 FragColor;
 struct Material {
     sampler2D diffuse;
     sampler2D specular;
+    sampler2D normal;
+    // Whether `normal` holds a tangent space normal map
+    bool hasNormalMap;
     float shininess;
+
+    vec3 ambientColor;
+    vec3 diffuseColor;
+    vec3 specularColor;
 };
 
 struct DirLight {
@@ -29,10 +36,16 @@ struct PointLight {
 };
 #define NR_POINT_LIGHTS 4
 uniform PointLight pointLights[NR_POINT_LIGHTS];
+// How many of pointLights are in use
+uniform int pointLightCount;
 
 in vec3 FragPos;
 in vec3 Normal;
 in vec2 TexCoords;
+in vec3 Tangent;
+in vec3 Bitangent;
+// Vertex color, white unless the model has some
+in vec3 Color;
 
 uniform vec3 viewPos;
 uniform Material material;
@@ -48,9 +61,9 @@ vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir) {
     float distance = length(light.position - fragPos);
     float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance * distance));
     // combine results
-    vec3 ambient = light.ambient * vec3(texture(material.diffuse, TexCoords));
-    vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, TexCoords));
-    vec3 specular = light.specular * spec * vec3(texture(material.specular, TexCoords));
+    vec3 ambient = light.ambient * material.ambientColor * Color * vec3(texture(material.diffuse, TexCoords));
+    vec3 diffuse = light.diffuse * diff * material.diffuseColor * Color * vec3(texture(material.diffuse, TexCoords));
+    vec3 specular = light.specular * spec * material.specularColor * vec3(texture(material.specular, TexCoords));
     ambient *= attenuation;
     diffuse *= attenuation;
     specular *= attenuation;
@@ -65,21 +78,25 @@ vec3 CalcDirLight(DirLight light, vec3 normal, vec3 viewDir) {
     vec3 reflectDir = reflect(-lightDir, normal);
     float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
     // combine results
-    vec3 ambient  = light.ambient  * vec3(texture(material.diffuse, TexCoords));
-    vec3 diffuse  = light.diffuse  * diff * vec3(texture(material.diffuse, TexCoords));
-    vec3 specular = light.specular * spec * vec3(texture(material.specular, TexCoords));
+    vec3 ambient  = light.ambient  * material.ambientColor * Color * vec3(texture(material.diffuse, TexCoords));
+    vec3 diffuse  = light.diffuse  * diff * material.diffuseColor * Color * vec3(texture(material.diffuse, TexCoords));
+    vec3 specular = light.specular * spec * material.specularColor * vec3(texture(material.specular, TexCoords));
     return (ambient + diffuse + specular);
 }
 
 void main() {
     // properties
     vec3 norm = normalize(Normal);
+    if (material.hasNormalMap) {
+        mat3 TBN = mat3(normalize(Tangent), normalize(Bitangent), norm);
+        norm = normalize(TBN * (texture(material.normal, TexCoords).rgb * 2.0 - 1.0));
+    }
     vec3 viewDir = normalize(viewPos - FragPos);
 
     // phase 1: Directional Light
     vec3 result = CalcDirLight(dirLight, norm, viewDir);
     // phase 2: Point Lights
-    for (int i = 0; i < NR_POINT_LIGHTS; i++) {
+    for (int i = 0; i < pointLightCount; i++) {
         result += CalcPointLight(pointLights[i], norm, FragPos, viewDir);
     }
 
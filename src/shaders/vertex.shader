@@ -2,6 +2,8 @@
 layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aNormal;
 layout (location = 2) in vec2 aTexCoords;
+layout (location = 3) in vec4 aTangent;
+layout (location = 4) in vec4 aColor;
 
 uniform mat4 model;
 uniform mat4 view;
@@ -10,11 +12,18 @@ uniform mat4 projection;
 out vec3 FragPos;
 out vec3 Normal;
 out vec2 TexCoords;
+out vec3 Tangent;
+out vec3 Bitangent;
+out vec3 Color;
 
 void main() {
+	mat3 normalMatrix = mat3(transpose(inverse(model)));
 	FragPos = vec3(model * vec4(aPos, 1.0));
-	Normal = mat3(transpose(inverse(model))) * aNormal;
+	Normal = normalMatrix * aNormal;
 	TexCoords = aTexCoords;
+	Tangent = mat3(model) * aTangent.xyz;
+	Bitangent = cross(Normal, Tangent) * aTangent.w;
+	Color = aColor.rgb;
 
-	gl_Position = projection * view * model * vec4(FragPos, 1.0);
+	gl_Position = projection * view * vec4(FragPos, 1.0);
 }
@@ -0,0 +1,402 @@
+use crate::scene::{FaceVertex, Scene, Triangle};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+    ops::Range,
+};
+
+// Symmetric 4x4 error quadric, stored as its upper triangle:
+// a2 ab ac ad / b2 bc bd / c2 cd / d2
+#[derive(Clone, Copy)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn zero() -> Self {
+        Quadric([0.; 10])
+    }
+
+    // Squared distance to the plane ax + by + cz + d = 0, times `weight`
+    fn from_plane(a: f64, b: f64, c: f64, d: f64, weight: f64) -> Self {
+        Quadric(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|q| q * weight),
+        )
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (q, o) in self.0.iter_mut().zip(other.0.iter()) {
+            *q += o;
+        }
+    }
+
+    fn error(&self, [x, y, z]: [f64; 3]) -> f64 {
+        let q = &self.0;
+        q[0] * x * x
+            + 2. * q[1] * x * y
+            + 2. * q[2] * x * z
+            + 2. * q[3] * x
+            + q[4] * y * y
+            + 2. * q[5] * y * z
+            + 2. * q[6] * y
+            + q[7] * z * z
+            + 2. * q[8] * z
+            + q[9]
+    }
+}
+
+// Moving vertex `from` onto vertex `to`, as of the given vertex stamps
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    stamps: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost == other.cost
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // Reversed, so that BinaryHeap pops the cheapest collapse first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+struct Simplifier<'a> {
+    scene: &'a Scene,
+    triangles: Vec<Triangle>,
+    alive: Vec<bool>,
+    alive_count: usize,
+    // Triangles around each vertex, dead ones included until the next cleanup
+    incident: Vec<Vec<usize>>,
+    quadrics: Vec<Quadric>,
+    // Vertices that must not move: borders, UV or normal seams, non-manifold
+    // edges
+    locked: Vec<bool>,
+    stamps: Vec<u32>,
+    heap: BinaryHeap<Collapse>,
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(scene: &'a Scene) -> Self {
+        let vertex_count = scene.vertices.len();
+        let mut simplifier = Self {
+            scene,
+            triangles: scene.triangles.clone(),
+            alive: vec![true; scene.triangles.len()],
+            alive_count: scene.triangles.len(),
+            incident: vec![Vec::new(); vertex_count],
+            quadrics: vec![Quadric::zero(); vertex_count],
+            locked: vec![false; vertex_count],
+            stamps: vec![0; vertex_count],
+            heap: BinaryHeap::new(),
+        };
+
+        let mut edges: HashMap<[usize; 2], usize> = HashMap::new();
+        let mut attributes: Vec<Option<(Option<usize>, Option<usize>)>> = vec![None; vertex_count];
+        for (t, triangle) in scene.triangles.iter().enumerate() {
+            let p = triangle
+                .corners
+                .map(|corner| simplifier.position(corner.vertex));
+            let normal = cross(sub(p[1], p[0]), sub(p[2], p[0]));
+            let length = dot(normal, normal).sqrt();
+            if length > 0. {
+                let [a, b, c] = normal.map(|n| n / length);
+                let d = -(a * p[0][0] + b * p[0][1] + c * p[0][2]);
+                // Weighted by area, so that slivers matter less
+                let quadric = Quadric::from_plane(a, b, c, d, length / 2.);
+                for corner in triangle.corners.iter() {
+                    simplifier.quadrics[corner.vertex].add(&quadric);
+                }
+            }
+            for (c, corner) in triangle.corners.iter().enumerate() {
+                simplifier.incident[corner.vertex].push(t);
+                let next = triangle.corners[(c + 1) % 3].vertex;
+                let edge = [corner.vertex.min(next), corner.vertex.max(next)];
+                *edges.entry(edge).or_insert(0) += 1;
+                let attribute = (corner.texture, corner.normal);
+                match attributes[corner.vertex] {
+                    None => attributes[corner.vertex] = Some(attribute),
+                    Some(seen) if seen != attribute => simplifier.locked[corner.vertex] = true,
+                    Some(_) => {}
+                }
+            }
+        }
+        for (edge, count) in edges {
+            if count != 2 {
+                simplifier.locked[edge[0]] = true;
+                simplifier.locked[edge[1]] = true;
+            }
+        }
+
+        for v in 0..vertex_count {
+            simplifier.push_collapses(v);
+        }
+        simplifier
+    }
+
+    fn position(&self, vertex: usize) -> [f64; 3] {
+        let v = &self.scene.vertices[vertex];
+        [v.x as f64, v.y as f64, v.z as f64]
+    }
+
+    fn neighbors(&self, vertex: usize) -> HashSet<usize> {
+        self.incident[vertex]
+            .iter()
+            .filter(|&&t| self.alive[t])
+            .flat_map(|&t| self.triangles[t].corners.map(|corner| corner.vertex))
+            .filter(|&v| v != vertex)
+            .collect()
+    }
+
+    // Queues every collapse of an edge touching `vertex`, both ways
+    fn push_collapses(&mut self, vertex: usize) {
+        for other in self.neighbors(vertex) {
+            for (from, to) in [(vertex, other), (other, vertex)] {
+                if self.locked[from] {
+                    continue;
+                }
+                let mut quadric = self.quadrics[from];
+                quadric.add(&self.quadrics[to]);
+                self.heap.push(Collapse {
+                    cost: quadric.error(self.position(to)).max(0.),
+                    from,
+                    to,
+                    stamps: (self.stamps[from], self.stamps[to]),
+                });
+            }
+        }
+    }
+
+    // Sorted vertices of triangle `t` once `from` has moved onto `to`
+    fn key(&self, t: usize, from: usize, to: usize) -> [usize; 3] {
+        let mut key = self.triangles[t].corners.map(|c| match c.vertex {
+            v if v == from => to,
+            v => v,
+        });
+        key.sort_unstable();
+        key
+    }
+
+    // Refuses collapses that would pinch the surface into a non-manifold one
+    // or turn a face over, or leave two faces on the same vertices
+    fn is_valid(&self, from: usize, to: usize) -> bool {
+        let shared: Vec<usize> = self.incident[from]
+            .iter()
+            .copied()
+            .filter(|&t| self.alive[t])
+            .filter(|&t| self.triangles[t].corners.iter().any(|c| c.vertex == to))
+            .collect();
+        if shared.is_empty() {
+            return false;
+        }
+        // Link condition: the only vertices next to both ends are the
+        // opposite corners of the faces along the edge
+        let common = self
+            .neighbors(from)
+            .intersection(&self.neighbors(to))
+            .count();
+        if common != shared.len() {
+            return false;
+        }
+        // Faces around `to`, which the moved ones must not double up with
+        let existing: HashSet<[usize; 3]> = self.incident[to]
+            .iter()
+            .filter(|&&t| self.alive[t])
+            .map(|&t| self.key(t, from, to))
+            .collect();
+        let target = self.position(to);
+        self.incident[from]
+            .iter()
+            .filter(|&&t| self.alive[t] && !shared.contains(&t))
+            .all(|&t| {
+                if existing.contains(&self.key(t, from, to)) {
+                    return false;
+                }
+                let p = self.triangles[t].corners.map(|c| self.position(c.vertex));
+                let moved = self.triangles[t].corners.map(|c| {
+                    if c.vertex == from {
+                        target
+                    } else {
+                        self.position(c.vertex)
+                    }
+                });
+                let before = cross(sub(p[1], p[0]), sub(p[2], p[0]));
+                let after = cross(sub(moved[1], moved[0]), sub(moved[2], moved[0]));
+                dot(before, after) > 0.
+            })
+    }
+
+    fn collapse(&mut self, from: usize, to: usize) {
+        let triangles: Vec<usize> = std::mem::take(&mut self.incident[from])
+            .into_iter()
+            .filter(|&t| self.alive[t])
+            .collect();
+        // The corner `to` has in a face along the edge, whose texture
+        // coordinates and normal carry over. `from` is not on a seam, so any
+        // of these faces gives the same ones.
+        let replacement: FaceVertex = *triangles
+            .iter()
+            .flat_map(|&t| self.triangles[t].corners.iter())
+            .find(|corner| corner.vertex == to)
+            .unwrap();
+        for &t in triangles.iter() {
+            let corners = &mut self.triangles[t].corners;
+            if corners.iter().any(|corner| corner.vertex == to) {
+                self.alive[t] = false;
+                self.alive_count -= 1;
+                continue;
+            }
+            for corner in corners.iter_mut().filter(|c| c.vertex == from) {
+                *corner = replacement;
+            }
+            self.incident[to].push(t);
+        }
+        let quadric = self.quadrics[from];
+        self.quadrics[to].add(&quadric);
+        self.incident[to].retain(|&t| self.alive[t]);
+
+        self.stamps[from] += 1;
+        self.stamps[to] += 1;
+        let neighbors = self.neighbors(to);
+        for &v in neighbors.iter() {
+            self.stamps[v] += 1;
+        }
+        self.push_collapses(to);
+        for v in neighbors {
+            self.push_collapses(v);
+        }
+    }
+
+    fn run(&mut self, target_triangles: usize, max_error: Option<f32>) {
+        let max_cost = max_error.map_or(f64::INFINITY, |e| (e as f64) * (e as f64));
+        while self.alive_count > target_triangles {
+            let Some(candidate) = self.heap.pop() else {
+                break;
+            };
+            let (from, to) = (candidate.from, candidate.to);
+            if candidate.stamps != (self.stamps[from], self.stamps[to]) {
+                // Stale, the neighborhood changed since it was queued
+                continue;
+            }
+            if candidate.cost > max_cost {
+                break;
+            }
+            if self.is_valid(from, to) {
+                self.collapse(from, to);
+            }
+        }
+    }
+
+    // Maps a range of the original triangles onto the surviving ones
+    fn remap(&self, range: &Range<usize>, kept_before: &[usize]) -> Range<usize> {
+        kept_before[range.start]..kept_before[range.end]
+    }
+
+    fn into_scene(self) -> Scene {
+        let mut kept_before = Vec::with_capacity(self.triangles.len() + 1);
+        let mut kept = 0;
+        for &alive in self.alive.iter() {
+            kept_before.push(kept);
+            kept += alive as usize;
+        }
+        kept_before.push(kept);
+
+        let mut scene = self.scene.clone();
+        scene.triangles = self
+            .triangles
+            .iter()
+            .zip(self.alive.iter())
+            .filter(|(_, &alive)| alive)
+            .map(|(triangle, _)| *triangle)
+            .collect();
+        for object in scene.objects.iter_mut() {
+            object.triangles = self.remap(&object.triangles, &kept_before);
+            for group in object.groups.iter_mut() {
+                group.triangles = self.remap(&group.triangles, &kept_before);
+            }
+            object.groups.retain(|group| !group.triangles.is_empty());
+        }
+        // Nodes follow their objects as emptied ones are dropped
+        let mut remaining = 0;
+        let objects: Vec<Option<usize>> = scene
+            .objects
+            .iter()
+            .map(|object| {
+                remaining += !object.is_empty() as usize;
+                (!object.is_empty()).then_some(remaining - 1)
+            })
+            .collect();
+        for node in scene.nodes.iter_mut() {
+            node.object = node.object.and_then(|object| objects[object]);
+        }
+        scene.objects.retain(|object| !object.is_empty());
+        scene
+    }
+}
+
+/// Decimates `scene` by quadric error edge collapses until it is down to
+/// `target_triangles` or the next collapse would move the surface further
+/// than `max_error`, in model units. Vertices only ever move onto one of
+/// their neighbors, so no new positions, texture coordinates or normals are
+/// made up, and border, seam and non-manifold vertices stay where they are.
+pub fn simplify(scene: &Scene, target_triangles: usize, max_error: Option<f32>) -> Scene {
+    let mut simplifier = Simplifier::new(scene);
+    simplifier.run(target_triangles, max_error);
+    simplifier.into_scene()
+}
+
+/// `scene` followed by up to `levels - 1` simplified versions of it, each
+/// aiming at `ratio` times the triangles of the previous one. The chain stops
+/// early once simplification no longer gets anywhere.
+pub fn lod_chain(scene: &Scene, levels: usize, ratio: f32) -> Vec<Scene> {
+    let mut chain = vec![scene.clone()];
+    while chain.len() < levels {
+        let previous = chain.last().unwrap();
+        let target = (previous.triangles.len() as f32 * ratio) as usize;
+        let next = simplify(previous, target, None);
+        if next.triangles.len() == previous.triangles.len() {
+            break;
+        }
+        chain.push(next);
+    }
+    chain
+}
@@ -0,0 +1,396 @@
+use crate::parser::{LineContext, ParseError, ParseErrorKind};
+use crate::scene::{FaceVertex, Normal, Object, Scene, Triangle, Vertex};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufRead, BufReader},
+};
+
+// Binary files start with an 80 byte header, often beginning with "solid" as
+// well, so that alone does not tell them apart from ASCII ones
+const HEADER_SIZE: usize = 80;
+const FACET_SIZE: usize = 50;
+
+/// STL parser, ASCII or binary, building the same `Scene` as the OBJ
+/// `Parser`. Facets sharing a position share its vertex, and the facet normal
+/// becomes the normal of all three corners.
+pub struct StlParser<R: BufRead> {
+    path: String,
+    reader: R,
+    // Size of the input when it is a file, which settles whether it is binary
+    length: Option<u64>,
+}
+
+// Builds the scene, welding positions and flat normals as they come
+struct Builder {
+    scene: Scene,
+    vertices: HashMap<[u32; 3], usize>,
+    normals: HashMap<[u32; 3], usize>,
+}
+
+impl Builder {
+    fn new() -> Self {
+        Self {
+            scene: Scene::new(),
+            vertices: HashMap::new(),
+            normals: HashMap::new(),
+        }
+    }
+
+    // -0.0 and 0.0 weld together
+    fn key(p: [f32; 3]) -> [u32; 3] {
+        p.map(|c| (c + 0.).to_bits())
+    }
+
+    fn vertex(&mut self, p: [f32; 3]) -> usize {
+        let vertices = &mut self.scene.vertices;
+        *self.vertices.entry(Self::key(p)).or_insert_with(|| {
+            vertices.push(Vertex::new(p[0], p[1], p[2], 1.));
+            vertices.len() - 1
+        })
+    }
+
+    // A zero normal, which some exporters write, is left for the viewer to
+    // compute
+    fn normal(&mut self, n: [f32; 3]) -> Option<usize> {
+        if n == [0.; 3] {
+            return None;
+        }
+        let normals = &mut self.scene.normals;
+        Some(*self.normals.entry(Self::key(n)).or_insert_with(|| {
+            normals.push(Normal::new(n[0], n[1], n[2]));
+            normals.len() - 1
+        }))
+    }
+
+    // Fans polygons with more than 3 corners, which a few ASCII exporters emit
+    fn facet(&mut self, normal: [f32; 3], corners: &[[f32; 3]]) {
+        let normal = self.normal(normal);
+        let corners: Vec<FaceVertex> = corners
+            .iter()
+            .map(|&p| FaceVertex {
+                vertex: self.vertex(p),
+                texture: None,
+                normal,
+            })
+            .collect();
+        for i in 1..corners.len() - 1 {
+            let triangle = Triangle::new([corners[0], corners[i], corners[i + 1]], 0, None);
+            self.scene.triangles.push(triangle);
+        }
+    }
+
+    fn start_solid(&mut self, name: &str) {
+        self.end_solid();
+        let start = self.scene.triangles.len();
+        self.scene.objects.push(Object::new(name, start));
+    }
+
+    fn end_solid(&mut self) {
+        let end = self.scene.triangles.len();
+        if let Some(object) = self.scene.objects.last_mut() {
+            object.triangles.end = end;
+        }
+    }
+
+    fn finish(mut self) -> Scene {
+        self.end_solid();
+        self.scene.objects.retain(|object| !object.is_empty());
+        self.scene
+    }
+}
+
+// Where the ASCII parser is within a solid
+enum State {
+    Outside,
+    Solid,
+    Facet([f32; 3]),
+    Loop([f32; 3], Vec<[f32; 3]>),
+    EndLoop([f32; 3], Vec<[f32; 3]>),
+}
+
+impl StlParser<BufReader<File>> {
+    pub fn new(filename: &str) -> Result<Self, ParseError> {
+        let file = File::open(filename).map_err(|e| ParseError::io(filename, 0, e))?;
+        let length = file
+            .metadata()
+            .map_err(|e| ParseError::io(filename, 0, e))?
+            .len();
+        Ok(Self {
+            length: Some(length),
+            ..Self::from_reader(BufReader::new(file), filename)
+        })
+    }
+}
+
+impl<R: BufRead> StlParser<R> {
+    /// `path` labels errors, e.g. "<stdin>".
+    pub fn from_reader(reader: R, path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            reader,
+            length: None,
+        }
+    }
+
+    // A binary file is exactly as long as its facet count says, which settles
+    // it when the length is known. Otherwise ASCII files start with "solid"
+    // and hold nothing but text, binary ones have float data past their
+    // header.
+    fn is_ascii(&mut self) -> Result<bool, ParseError> {
+        let start = self
+            .reader
+            .fill_buf()
+            .map_err(|e| ParseError::io(&self.path, 0, e))?;
+        let count = start
+            .get(HEADER_SIZE..HEADER_SIZE + 4)
+            .map(|count| u32::from_le_bytes(count.try_into().unwrap()) as u64);
+        if let (Some(length), Some(count)) = (self.length, count) {
+            if length == (HEADER_SIZE + 4) as u64 + FACET_SIZE as u64 * count {
+                return Ok(false);
+            }
+        }
+        let start = &start[..start.len().min(HEADER_SIZE + FACET_SIZE * 4)];
+        let text = |&byte: &u8| byte.is_ascii_graphic() || byte.is_ascii_whitespace();
+        Ok(start.trim_ascii_start().starts_with(b"solid") && start.iter().all(text))
+    }
+
+    pub fn generate_scene(mut self) -> Result<Scene, ParseError> {
+        if self.is_ascii()? {
+            self.parse_ascii()
+        } else {
+            self.parse_binary()
+        }
+    }
+
+    // 80 byte header, facet count, then per facet the normal and corners as
+    // 12 little endian floats and a 2 byte attribute, ignored
+    fn parse_binary(mut self) -> Result<Scene, ParseError> {
+        let mut header = [0; HEADER_SIZE + 4];
+        self.reader
+            .read_exact(&mut header)
+            .map_err(|e| ParseError::io(&self.path, 0, e))?;
+        let count = u32::from_le_bytes(header[HEADER_SIZE..].try_into().unwrap());
+        let mut builder = Builder::new();
+        builder.start_solid("");
+        let mut facet = [0; FACET_SIZE];
+        for _ in 0..count {
+            self.reader
+                .read_exact(&mut facet)
+                .map_err(|e| ParseError::io(&self.path, 0, e))?;
+            let float = |i: usize| f32::from_le_bytes(facet[i * 4..i * 4 + 4].try_into().unwrap());
+            let point = |i: usize| [float(i * 3), float(i * 3 + 1), float(i * 3 + 2)];
+            builder.facet(point(0), &[point(1), point(2), point(3)]);
+        }
+        Ok(builder.finish())
+    }
+
+    fn parse_ascii(mut self) -> Result<Scene, ParseError> {
+        let mut builder = Builder::new();
+        let mut state = State::Outside;
+        let mut line = String::new();
+        let mut number: usize = 0;
+        // Last non-blank line, where a facet cut short is reported
+        let mut last = (0, String::new());
+
+        loop {
+            line.clear();
+            number += 1;
+            let read = self
+                .reader
+                .read_line(&mut line)
+                .map_err(|e| ParseError::io(&self.path, number, e))?;
+            if read == 0 {
+                break;
+            }
+            let line = line.trim_end();
+            let split_line: Vec<&str> = line.split_whitespace().collect();
+            if split_line.is_empty() {
+                continue;
+            }
+            let ctx = LineContext {
+                path: &self.path,
+                number,
+                line,
+            };
+            state = self.parse_line(&ctx, &split_line, state, &mut builder)?;
+            last = (number, line.to_string());
+        }
+        // A missing endsolid is forgiven, not a facet cut short
+        match state {
+            State::Outside | State::Solid => Ok(builder.finish()),
+            _ => {
+                let ctx = LineContext {
+                    path: &self.path,
+                    number: last.0,
+                    line: &last.1,
+                };
+                let kind = ParseErrorKind::MissingComponent("endfacet".to_string());
+                Err(ctx.error_at_end(kind))
+            }
+        }
+    }
+
+    // solid [name] / facet normal x y z / outer loop / vertex x y z... /
+    // endloop / endfacet / endsolid [name]
+    fn parse_line(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+        state: State,
+        builder: &mut Builder,
+    ) -> Result<State, ParseError> {
+        let keyword = split_line[0];
+        let unexpected = || ctx.error(ParseErrorKind::MalformedFace, keyword);
+        Ok(match (keyword, state) {
+            ("solid", State::Outside) => {
+                builder.start_solid(&split_line[1..].join(" "));
+                State::Solid
+            }
+            ("facet", State::Solid) => {
+                if split_line.get(1) != Some(&"normal") {
+                    return Err(
+                        ctx.error_at_end(ParseErrorKind::MissingComponent("normal".to_string()))
+                    );
+                }
+                State::Facet(self.parse_point(ctx, split_line, 2, "normal")?)
+            }
+            ("outer", State::Facet(normal)) => {
+                if split_line.get(1) != Some(&"loop") || split_line.len() > 2 {
+                    return Err(unexpected());
+                }
+                State::Loop(normal, Vec::new())
+            }
+            ("vertex", State::Loop(normal, mut corners)) => {
+                corners.push(self.parse_point(ctx, split_line, 1, "vertex")?);
+                State::Loop(normal, corners)
+            }
+            ("endloop", State::Loop(normal, corners)) => {
+                if corners.len() < 3 {
+                    return Err(ctx.error(
+                        ParseErrorKind::MissingComponent(
+                            "vertices in facet, at least 3 needed".to_string(),
+                        ),
+                        keyword,
+                    ));
+                }
+                State::EndLoop(normal, corners)
+            }
+            ("endfacet", State::EndLoop(normal, corners)) => {
+                builder.facet(normal, &corners);
+                State::Solid
+            }
+            ("endsolid", State::Solid) => {
+                builder.end_solid();
+                State::Outside
+            }
+            ("solid" | "facet" | "outer" | "vertex" | "endloop" | "endfacet" | "endsolid", _) => {
+                return Err(unexpected())
+            }
+            _ => return Err(ctx.error(ParseErrorKind::UnknownDirective, keyword)),
+        })
+    }
+
+    // Three floats from `split_line[start..]`, and nothing after them
+    fn parse_point(
+        &self,
+        ctx: &LineContext,
+        split_line: &[&str],
+        start: usize,
+        element: &str,
+    ) -> Result<[f32; 3], ParseError> {
+        let mut point = [0.; 3];
+        for (i, name) in ["x", "y", "z"].into_iter().enumerate() {
+            let token = split_line.get(start + i).ok_or_else(|| {
+                ctx.error_at_end(ParseErrorKind::MissingComponent(format!(
+                    "{} component in {}",
+                    name, element
+                )))
+            })?;
+            point[i] = token
+                .parse::<f32>()
+                .map_err(|_| ctx.error(ParseErrorKind::BadNumber, token))?;
+        }
+        if let Some(token) = split_line.get(start + 3) {
+            return Err(ctx.error(
+                ParseErrorKind::TooManyComponents(element.to_string()),
+                token,
+            ));
+        }
+        Ok(point)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(bytes: &[u8], length: Option<u64>) -> Scene {
+        StlParser {
+            length,
+            ..StlParser::from_reader(bytes, "<test>")
+        }
+        .generate_scene()
+        .unwrap()
+    }
+
+    // Two facets sharing an edge, the second with a zero normal
+    const FACETS: [([f32; 3], [[f32; 3]; 3]); 2] = [
+        ([0., 0., 1.], [[0., 0., 0.], [1., 0., 0.], [0., 1., 0.]]),
+        ([0., 0., 0.], [[1., 0., 0.], [1., 1., 0.], [0., 1., 0.]]),
+    ];
+
+    fn check(scene: &Scene) {
+        assert_eq!(scene.vertices.len(), 4);
+        assert_eq!(scene.normals.len(), 1);
+        assert_eq!(scene.objects.len(), 1);
+        let corners: Vec<[(usize, Option<usize>); 3]> = scene
+            .triangles
+            .iter()
+            .map(|t| t.corners.map(|c| (c.vertex, c.normal)))
+            .collect();
+        assert_eq!(
+            corners,
+            [
+                [(0, Some(0)), (1, Some(0)), (2, Some(0))],
+                [(1, None), (3, None), (2, None)]
+            ]
+        );
+        let v = &scene.vertices[3];
+        assert_eq!((v.x, v.y, v.z), (1., 1., 0.));
+    }
+
+    #[test]
+    fn ascii_facets_share_their_vertices() {
+        let mut text = String::from("solid square\n");
+        for (normal, corners) in FACETS {
+            text += &format!(
+                "  facet normal {} {} {}\n    outer loop\n",
+                normal[0], normal[1], normal[2]
+            );
+            for p in corners {
+                text += &format!("      vertex {} {} {}\n", p[0], p[1], p[2]);
+            }
+            text += "    endloop\n  endfacet\n";
+        }
+        text += "endsolid square\n";
+        let scene = parse(text.as_bytes(), Some(text.len() as u64));
+        assert_eq!(scene.objects[0].name, "square");
+        check(&scene);
+    }
+
+    #[test]
+    fn binary_files_may_start_with_solid() {
+        let mut bytes = b"solid square, binary".to_vec();
+        bytes.resize(HEADER_SIZE, b' ');
+        bytes.extend((FACETS.len() as u32).to_le_bytes());
+        for (normal, corners) in FACETS {
+            for c in [normal].iter().chain(corners.iter()).flatten() {
+                bytes.extend(c.to_le_bytes());
+            }
+            bytes.extend([0, 0]);
+        }
+        check(&parse(&bytes, Some(bytes.len() as u64)));
+        check(&parse(&bytes, None));
+    }
+}
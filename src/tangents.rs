@@ -0,0 +1,105 @@
+use crate::indexing::IndexedScene;
+use crate::normals::unit;
+use crate::scene::{FaceVertex, Scene};
+use crate::tuple::{cross, dot, magnitude, vector, Vector};
+
+// `v` with its component along the unit vector `n` removed
+fn project(v: Vector, n: Vector) -> Vector {
+    v - n * dot(n, v)
+}
+
+// Some direction perpendicular to the unit vector `n`
+fn any_perpendicular(n: Vector) -> Vector {
+    let other = if n.x.abs() < 0.9 {
+        vector(1., 0., 0.)
+    } else {
+        vector(0., 1., 0.)
+    };
+    unit(cross(other, n))
+}
+
+fn position(scene: &Scene, corner: &FaceVertex) -> Vector {
+    let v = &scene.vertices[corner.vertex];
+    vector(v.x, v.y, v.z)
+}
+
+fn normal(scene: &Scene, corner: &FaceVertex, fallback: Vector) -> Vector {
+    match corner.normal {
+        Some(i) => {
+            let n = &scene.normals[i];
+            unit(vector(n.x, n.y, n.z))
+        }
+        None => fallback,
+    }
+}
+
+fn uv(scene: &Scene, corner: &FaceVertex) -> Option<(f32, f32)> {
+    let t = &scene.texture_coordinates[corner.texture?];
+    Some((t.u, t.v))
+}
+
+/// Tangent of every vertex of `indexed`, following the MikkTSpace
+/// conventions: xyz is the tangent, orthogonal to the vertex normal, and w
+/// the handedness, so that the bitangent is `w * cross(normal, tangent)`.
+/// Faces contribute their UV-space tangent frame, projected onto each
+/// vertex's normal plane and weighted by the corner angle. Vertices without
+/// texture coordinates get an arbitrary tangent.
+pub fn generate_tangents(scene: &Scene, indexed: &IndexedScene) -> Vec<[f32; 4]> {
+    let zero = vector(0., 0., 0.);
+    let mut tangents = vec![zero; indexed.vertices.len()];
+    let mut bitangents = vec![zero; indexed.vertices.len()];
+    let mut normals: Vec<Option<Vector>> = vec![None; indexed.vertices.len()];
+
+    for t in 0..indexed.triangle_indices / 3 {
+        let vertices = [0, 1, 2].map(|c| indexed.indices.get(t * 3 + c));
+        let corners = vertices.map(|v| indexed.vertices[v]);
+        let p = corners.map(|corner| position(scene, &corner));
+        let face = unit(cross(p[1] - p[0], p[2] - p[0]));
+        for c in 0..3 {
+            normals[vertices[c]].get_or_insert(normal(scene, &corners[c], face));
+        }
+
+        let (Some(uv0), Some(uv1), Some(uv2)) = (
+            uv(scene, &corners[0]),
+            uv(scene, &corners[1]),
+            uv(scene, &corners[2]),
+        ) else {
+            continue;
+        };
+        let (e1, e2) = (p[1] - p[0], p[2] - p[0]);
+        let (du1, dv1) = (uv1.0 - uv0.0, uv1.1 - uv0.1);
+        let (du2, dv2) = (uv2.0 - uv0.0, uv2.1 - uv0.1);
+        let determinant = du1 * dv2 - du2 * dv1;
+        if determinant == 0. {
+            // Degenerate in UV space, no direction to give
+            continue;
+        }
+        let tangent = (e1 * dv2 - e2 * dv1) / determinant;
+        let bitangent = (e2 * du1 - e1 * du2) / determinant;
+
+        for c in 0..3 {
+            let (a, b) = (p[(c + 1) % 3] - p[c], p[(c + 2) % 3] - p[c]);
+            let angle = dot(unit(a), unit(b)).clamp(-1., 1.).acos();
+            let n = normals[vertices[c]].unwrap();
+            let v = vertices[c];
+            tangents[v] = tangents[v] + unit(project(tangent, n)) * angle;
+            bitangents[v] = bitangents[v] + unit(project(bitangent, n)) * angle;
+        }
+    }
+
+    (0..indexed.vertices.len())
+        .map(|v| {
+            let n = normals[v].unwrap_or(vector(0., 0., 1.));
+            let mut tangent = unit(project(tangents[v], n));
+            if magnitude(tangent) == 0. {
+                tangent = any_perpendicular(n);
+            }
+            let handedness = if dot(cross(n, tangent), bitangents[v]) < 0. {
+                -1.
+            } else {
+                1.
+            };
+            [tangent.x, tangent.y, tangent.z, handedness]
+        })
+        .collect()
+}
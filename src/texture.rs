@@ -0,0 +1,72 @@
+use gl::types::GLuint;
+use image::ImageResult;
+use std::{ffi::c_void, path::Path};
+
+unsafe fn upload(width: u32, height: u32, data: &[u8]) -> GLuint {
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+
+    // Texture wrapping
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+
+    // texture filtering
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+
+    gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        gl::RGBA as i32,
+        width as i32,
+        height as i32,
+        0,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        data.as_ptr() as *const c_void,
+    );
+    gl::GenerateMipmap(gl::TEXTURE_2D);
+    texture
+}
+
+pub fn load_texture(path: &Path) -> ImageResult<GLuint> {
+    let img = image::open(path)?.to_rgba8();
+    Ok(unsafe { upload(img.width(), img.height(), img.as_raw()) })
+}
+
+/// 1x1 white texture, so that sampling a missing map leaves the material
+/// colors untouched.
+pub fn white_texture() -> GLuint {
+    unsafe { upload(1, 1, &[255, 255, 255, 255]) }
+}
+
+/// Black and white checkerboard of 8x8 squares, to inspect how texture
+/// coordinates stretch over a model.
+pub fn checker_texture() -> GLuint {
+    const SIZE: u32 = 256;
+    const SQUARE: u32 = SIZE / 8;
+    let data: Vec<u8> = (0..SIZE * SIZE)
+        .flat_map(|i| {
+            let (x, y) = (i % SIZE / SQUARE, i / SIZE / SQUARE);
+            let shade = if (x + y) % 2 == 0 { 255 } else { 32 };
+            [shade, shade, shade, 255]
+        })
+        .collect();
+    unsafe { upload(SIZE, SIZE, &data) }
+}
+
+/// Loads `path` if there is one, falling back to a white texture when it is
+/// missing or unreadable.
+pub fn load_texture_or_white(path: Option<&Path>) -> GLuint {
+    let Some(path) = path else {
+        return white_texture();
+    };
+    match load_texture(path) {
+        Ok(texture) => texture,
+        Err(e) => {
+            eprintln!("Failed to load texture {}: {}", path.display(), e);
+            white_texture()
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::normals::face_normal;
+use crate::scene::{BoundingBox, Scene, TextureCoordinate};
+use crate::tuple::{vector, Vector};
+use std::{collections::HashMap, f32::consts::PI};
+
+/// How positions are mapped to texture coordinates, all of them fitted to the
+/// model's bounding box.
+#[derive(Clone, Copy)]
+pub enum Projection {
+    /// Straight down the box's thinnest axis.
+    Planar,
+    /// Longitude and latitude around the box's center, Y up.
+    Spherical,
+    /// Angle around and height along the box's longest axis.
+    Cylindrical,
+    /// Each face projected along the axis its normal is closest to, like
+    /// triplanar mapping.
+    Box,
+}
+
+struct Bounds {
+    min: Vector,
+    size: Vector,
+}
+
+fn axis(v: Vector, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+fn bounds(bounding_box: BoundingBox) -> Bounds {
+    let size = bounding_box.size();
+    // Flat models would divide by zero otherwise
+    let size = [size.x, size.y, size.z].map(|s| s.max(f32::EPSILON));
+    Bounds {
+        min: bounding_box.min,
+        size: vector(size[0], size[1], size[2]),
+    }
+}
+
+impl Bounds {
+    // Position scaled into [0, 1] on every axis
+    fn normalize(&self, p: Vector) -> [f32; 3] {
+        [0, 1, 2].map(|i| (axis(p, i) - axis(self.min, i)) / axis(self.size, i))
+    }
+
+    // Position relative to the center, in [-1, 1] on every axis
+    fn centered(&self, p: Vector) -> [f32; 3] {
+        self.normalize(p).map(|t| t * 2. - 1.)
+    }
+
+    fn thinnest_axis(&self) -> usize {
+        (0..3)
+            .min_by(|&a, &b| axis(self.size, a).total_cmp(&axis(self.size, b)))
+            .unwrap()
+    }
+
+    fn longest_axis(&self) -> usize {
+        (0..3)
+            .max_by(|&a, &b| axis(self.size, a).total_cmp(&axis(self.size, b)))
+            .unwrap()
+    }
+}
+
+// The two axes left once `axis` is projected away, in a right-handed order
+fn plane(axis: usize) -> (usize, usize) {
+    ((axis + 1) % 3, (axis + 2) % 3)
+}
+
+// Angle around `axis`, in [0, 1)
+fn around(p: [f32; 3], axis: usize) -> f32 {
+    let (a, b) = plane(axis);
+    p[b].atan2(p[a]) / (2. * PI) + 0.5
+}
+
+/// Gives texture coordinates to every corner missing some. Corners share
+/// coordinates wherever they come out identical, so the mesh only splits
+/// along the projection's seams.
+pub fn generate_uvs(scene: &mut Scene, projection: Projection) {
+    let Some(bounding_box) = scene.bounding_box() else {
+        return;
+    };
+    let bounds = bounds(bounding_box);
+    let mut indices: HashMap<[u32; 2], usize> = HashMap::new();
+    for i in 0..scene.triangles.len() {
+        let triangle = scene.triangles[i];
+        if triangle
+            .corners
+            .iter()
+            .all(|corner| corner.texture.is_some())
+        {
+            continue;
+        }
+        let positions = triangle.corners.map(|corner| {
+            let v = &scene.vertices[corner.vertex];
+            vector(v.x, v.y, v.z)
+        });
+        let mut uvs = match projection {
+            Projection::Planar => {
+                let (a, b) = plane(bounds.thinnest_axis());
+                positions.map(|p| {
+                    let p = bounds.normalize(p);
+                    [p[a], p[b]]
+                })
+            }
+            Projection::Spherical => positions.map(|p| {
+                let p = bounds.centered(p);
+                let length = (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt();
+                let latitude = if length == 0. {
+                    0.
+                } else {
+                    (p[1] / length).clamp(-1., 1.).asin()
+                };
+                [around(p, 1), latitude / PI + 0.5]
+            }),
+            Projection::Cylindrical => {
+                let up = bounds.longest_axis();
+                positions.map(|p| {
+                    let height = bounds.normalize(p)[up];
+                    [around(bounds.centered(p), up), height]
+                })
+            }
+            Projection::Box => {
+                let normal = face_normal(scene, &triangle);
+                let normal = [normal.x.abs(), normal.y.abs(), normal.z.abs()];
+                let dominant = (0..3)
+                    .max_by(|&a, &b| normal[a].total_cmp(&normal[b]))
+                    .unwrap();
+                let (a, b) = plane(dominant);
+                positions.map(|p| {
+                    let p = bounds.normalize(p);
+                    [p[a], p[b]]
+                })
+            }
+        };
+        if let Projection::Spherical | Projection::Cylindrical = projection {
+            // Faces straddling the seam would otherwise wrap back across the
+            // whole texture
+            let max = uvs.iter().map(|uv| uv[0]).fold(0., f32::max);
+            for uv in uvs.iter_mut() {
+                if max - uv[0] > 0.5 {
+                    uv[0] += 1.;
+                }
+            }
+        }
+        for (c, uv) in uvs.into_iter().enumerate() {
+            if scene.triangles[i].corners[c].texture.is_some() {
+                continue;
+            }
+            let index = *indices.entry(uv.map(f32::to_bits)).or_insert_with(|| {
+                scene
+                    .texture_coordinates
+                    .push(TextureCoordinate::new(uv[0], uv[1], 0.));
+                scene.texture_coordinates.len() - 1
+            });
+            scene.triangles[i].corners[c].texture = Some(index);
+        }
+    }
+}
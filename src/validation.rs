@@ -0,0 +1,167 @@
+use crate::normals::face_normal;
+use crate::scene::{Scene, Triangle};
+use crate::tuple::{magnitude, vector};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+// Items listed per problem when printing, the counts are always complete
+const SHOWN_ITEMS: usize = 8;
+
+// Area below which a triangle counts as degenerate, relative to its longest
+// edge so that the check does not depend on the model's scale
+const DEGENERATE_RATIO: f32 = 1e-6;
+
+/// Topology problems found in a `Scene`. Triangles are indices into
+/// `Scene::triangles`, vertices into `Scene::vertices` and edges are pairs of
+/// vertex indices, smallest first.
+pub struct ValidationReport {
+    /// Zero-area triangles, including those using a vertex twice.
+    pub degenerate_triangles: Vec<usize>,
+    /// Triangles over the same three vertices as an earlier one, as
+    /// (duplicate, original).
+    pub duplicate_triangles: Vec<(usize, usize)>,
+    /// Triangles with a position, texture or normal index past its array.
+    pub out_of_range_triangles: Vec<usize>,
+    /// Vertices no triangle, line or point refers to.
+    pub unused_vertices: Vec<usize>,
+    /// Edges shared by more than two triangles.
+    pub non_manifold_edges: Vec<[usize; 2]>,
+    /// Edges with a single triangle, bordering a hole or an open surface.
+    pub boundary_edges: Vec<[usize; 2]>,
+    /// Edges whose two triangles run along them in the same direction, so
+    /// that one of them faces the wrong way.
+    pub inconsistent_edges: Vec<[usize; 2]>,
+}
+
+fn in_range(scene: &Scene, triangle: &Triangle) -> bool {
+    triangle.corners.iter().all(|corner| {
+        corner.vertex < scene.vertices.len()
+            && corner
+                .texture
+                .is_none_or(|i| i < scene.texture_coordinates.len())
+            && corner.normal.is_none_or(|i| i < scene.normals.len())
+    })
+}
+
+fn is_degenerate(scene: &Scene, triangle: &Triangle) -> bool {
+    let [a, b, c] = triangle.corners.map(|corner| corner.vertex);
+    if a == b || b == c || a == c {
+        return true;
+    }
+    let position = |i: usize| {
+        let v = &scene.vertices[i];
+        vector(v.x, v.y, v.z)
+    };
+    let longest = [(a, b), (b, c), (c, a)]
+        .iter()
+        .map(|&(from, to)| magnitude(position(to) - position(from)))
+        .fold(0., f32::max);
+    magnitude(face_normal(scene, triangle)) <= DEGENERATE_RATIO * longest * longest
+}
+
+impl ValidationReport {
+    pub fn new(scene: &Scene) -> Self {
+        let mut report = Self {
+            degenerate_triangles: Vec::new(),
+            duplicate_triangles: Vec::new(),
+            out_of_range_triangles: Vec::new(),
+            unused_vertices: Vec::new(),
+            non_manifold_edges: Vec::new(),
+            boundary_edges: Vec::new(),
+            inconsistent_edges: Vec::new(),
+        };
+
+        let mut used: HashSet<usize> = scene.lines.iter().flatten().copied().collect();
+        used.extend(scene.points.iter().copied());
+        let mut faces: HashMap<[usize; 3], usize> = HashMap::new();
+        // Every triangle running along each edge, with whether it goes from
+        // the smallest vertex to the largest
+        let mut edges: HashMap<[usize; 2], Vec<bool>> = HashMap::new();
+
+        for (i, triangle) in scene.triangles.iter().enumerate() {
+            let vertices = triangle.corners.map(|corner| corner.vertex);
+            used.extend(vertices);
+            if !in_range(scene, triangle) {
+                report.out_of_range_triangles.push(i);
+                continue;
+            }
+            if is_degenerate(scene, triangle) {
+                report.degenerate_triangles.push(i);
+            }
+            let mut key = vertices;
+            key.sort_unstable();
+            if let Some(&original) = faces.get(&key) {
+                report.duplicate_triangles.push((i, original));
+            } else {
+                faces.insert(key, i);
+            }
+            for c in 0..3 {
+                let (from, to) = (vertices[c], vertices[(c + 1) % 3]);
+                if from != to {
+                    let edge = [from.min(to), from.max(to)];
+                    edges.entry(edge).or_default().push(from < to);
+                }
+            }
+        }
+
+        report.unused_vertices = (0..scene.vertices.len())
+            .filter(|i| !used.contains(i))
+            .collect();
+        for (edge, directions) in edges {
+            match directions.as_slice() {
+                [_] => report.boundary_edges.push(edge),
+                [a, b] if a == b => report.inconsistent_edges.push(edge),
+                [_, _] => {}
+                _ => report.non_manifold_edges.push(edge),
+            }
+        }
+        // Edges come out of a HashMap, sort them for stable output
+        report.boundary_edges.sort_unstable();
+        report.inconsistent_edges.sort_unstable();
+        report.non_manifold_edges.sort_unstable();
+        report
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.degenerate_triangles.is_empty()
+            && self.duplicate_triangles.is_empty()
+            && self.out_of_range_triangles.is_empty()
+            && self.unused_vertices.is_empty()
+            && self.non_manifold_edges.is_empty()
+            && self.boundary_edges.is_empty()
+            && self.inconsistent_edges.is_empty()
+    }
+}
+
+// One line per problem: its count, then the first few offenders
+fn write_items<T: fmt::Debug>(f: &mut fmt::Formatter<'_>, name: &str, items: &[T]) -> fmt::Result {
+    write!(f, "{:>24}: {}", name, items.len())?;
+    if !items.is_empty() {
+        let shown: Vec<String> = items
+            .iter()
+            .take(SHOWN_ITEMS)
+            .map(|item| format!("{:?}", item))
+            .collect();
+        write!(f, " ({}", shown.join(", "))?;
+        if items.len() > SHOWN_ITEMS {
+            write!(f, ", ...")?;
+        }
+        write!(f, ")")?;
+    }
+    writeln!(f)
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_items(f, "degenerate triangles", &self.degenerate_triangles)?;
+        write_items(f, "duplicate triangles", &self.duplicate_triangles)?;
+        write_items(f, "out of range triangles", &self.out_of_range_triangles)?;
+        write_items(f, "unused vertices", &self.unused_vertices)?;
+        write_items(f, "non-manifold edges", &self.non_manifold_edges)?;
+        write_items(f, "boundary edges", &self.boundary_edges)?;
+        write_items(f, "inconsistent winding", &self.inconsistent_edges)?;
+        write!(f, "{}", if self.is_clean() { "OK" } else { "FAILED" })
+    }
+}
@@ -0,0 +1,305 @@
+use crate::scene::{Color, FaceVertex, Material, Scene};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// How numbers are written out. `precision` is the number of decimals kept,
+/// trailing zeros are dropped.
+#[derive(Clone, Copy)]
+pub struct WriteOptions {
+    pub precision: usize,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self { precision: 6 }
+    }
+}
+
+impl WriteOptions {
+    fn float(&self, value: f32) -> String {
+        let text = format!("{:.*}", self.precision, value);
+        let text = if text.contains('.') {
+            text.trim_end_matches('0').trim_end_matches('.')
+        } else {
+            &text
+        };
+        match text {
+            "-0" => "0".to_string(),
+            _ => text.to_string(),
+        }
+    }
+
+    fn color(&self, color: Color) -> String {
+        format!(
+            "{} {} {}",
+            self.float(color.r),
+            self.float(color.g),
+            self.float(color.b)
+        )
+    }
+}
+
+/// Writes `scene` to `path` and, when it has materials, its library next to it
+/// with the same name and an `.mtl` extension.
+pub fn save(scene: &Scene, path: &Path, options: &WriteOptions) -> io::Result<()> {
+    let directory = path.parent().unwrap_or(Path::new(""));
+    let mut library = None;
+    if !scene.materials.is_empty() {
+        let mtl_path = path.with_extension("mtl");
+        let file = BufWriter::new(File::create(&mtl_path)?);
+        write_mtl(&scene.materials, file, directory, options)?;
+        library = mtl_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned());
+    }
+    let file = BufWriter::new(File::create(path)?);
+    write_obj(scene, file, library.as_deref(), options)
+}
+
+// v, v/vt, v//vn or v/vt/vn, 1-based
+fn corner(corner: &FaceVertex) -> String {
+    let vertex = corner.vertex + 1;
+    match (corner.texture, corner.normal) {
+        (None, None) => format!("{}", vertex),
+        (Some(t), None) => format!("{}/{}", vertex, t + 1),
+        (None, Some(n)) => format!("{}//{}", vertex, n + 1),
+        (Some(t), Some(n)) => format!("{}/{}/{}", vertex, t + 1, n + 1),
+    }
+}
+
+/// Writes `scene` as OBJ, referencing `material_library` if given. Faces keep
+/// their objects, groups, smoothing groups and materials, so that parsing the
+/// output gives back the same scene. Lines are joined back into polylines.
+pub fn write_obj<W: Write>(
+    scene: &Scene,
+    mut out: W,
+    material_library: Option<&str>,
+    options: &WriteOptions,
+) -> io::Result<()> {
+    let f = |value: f32| options.float(value);
+    if let Some(library) = material_library {
+        writeln!(out, "mtllib {}", library)?;
+    }
+    for v in scene.vertices.iter() {
+        if v.w == 1. {
+            writeln!(out, "v {} {} {}", f(v.x), f(v.y), f(v.z))?;
+        } else {
+            writeln!(out, "v {} {} {} {}", f(v.x), f(v.y), f(v.z), f(v.w))?;
+        }
+    }
+    for vt in scene.texture_coordinates.iter() {
+        if vt.w == 0. {
+            writeln!(out, "vt {} {}", f(vt.u), f(vt.v))?;
+        } else {
+            writeln!(out, "vt {} {} {}", f(vt.u), f(vt.v), f(vt.w))?;
+        }
+    }
+    for vn in scene.normals.iter() {
+        writeln!(out, "vn {} {} {}", f(vn.x), f(vn.y), f(vn.z))?;
+    }
+
+    // `o` and `g` lines by the triangle they come before. An unnamed object
+    // is the one the parser makes up for faces before any `o`.
+    let mut headers: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for object in scene.objects.iter() {
+        if !object.name.is_empty() {
+            let lines = headers.entry(object.triangles.start).or_default();
+            lines.push(format!("o {}", object.name));
+        }
+        for group in object.groups.iter() {
+            let lines = headers.entry(group.triangles.start).or_default();
+            lines.push(format!("g {}", group.names.join(" ")));
+        }
+    }
+
+    // State the parser will be in when reading each face back
+    let mut smoothing_group = 0;
+    let mut material = None;
+    for (i, triangle) in scene.triangles.iter().enumerate() {
+        for line in headers.get(&i).into_iter().flatten() {
+            writeln!(out, "{}", line)?;
+        }
+        if triangle.smoothing_group != smoothing_group {
+            smoothing_group = triangle.smoothing_group;
+            match smoothing_group {
+                0 => writeln!(out, "s off")?,
+                _ => writeln!(out, "s {}", smoothing_group)?,
+            }
+        }
+        // Going back to no material cannot be written, the last one stays
+        if let Some(index) = triangle.material.filter(|&m| material != Some(m)) {
+            material = Some(index);
+            writeln!(out, "usemtl {}", scene.materials[index].name)?;
+        }
+        let [a, b, c] = triangle.corners;
+        writeln!(out, "f {} {} {}", corner(&a), corner(&b), corner(&c))?;
+    }
+
+    let mut polyline: Vec<usize> = Vec::new();
+    for &[a, b] in scene.lines.iter() {
+        if polyline.last() != Some(&a) {
+            write_element(&mut out, "l", &polyline)?;
+            polyline = vec![a];
+        }
+        polyline.push(b);
+    }
+    write_element(&mut out, "l", &polyline)?;
+    write_element(&mut out, "p", &scene.points)?;
+    out.flush()
+}
+
+// l/p followed by 1-based vertex indices, nothing if there are none
+fn write_element<W: Write>(out: &mut W, keyword: &str, vertices: &[usize]) -> io::Result<()> {
+    if vertices.is_empty() {
+        return Ok(());
+    }
+    let indices: Vec<String> = vertices.iter().map(|v| (v + 1).to_string()).collect();
+    writeln!(out, "{} {}", keyword, indices.join(" "))
+}
+
+// Texture maps are stored resolved, so they are written relative to
+// `directory` when they live under it, and absolute otherwise
+fn map_path(map: &Path, directory: &Path) -> PathBuf {
+    let (Ok(map), Ok(directory)) = (fs::canonicalize(map), fs::canonicalize(directory)) else {
+        return map.to_path_buf();
+    };
+    match map.strip_prefix(&directory) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => map,
+    }
+}
+
+/// Writes `materials` as an MTL library to be saved in `directory`.
+pub fn write_mtl<W: Write>(
+    materials: &[Material],
+    mut out: W,
+    directory: &Path,
+    options: &WriteOptions,
+) -> io::Result<()> {
+    for (i, material) in materials.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "newmtl {}", material.name)?;
+        writeln!(out, "Ka {}", options.color(material.ambient))?;
+        writeln!(out, "Kd {}", options.color(material.diffuse))?;
+        writeln!(out, "Ks {}", options.color(material.specular))?;
+        writeln!(out, "Ke {}", options.color(material.emissive))?;
+        writeln!(out, "Ns {}", options.float(material.shininess))?;
+        writeln!(out, "Ni {}", options.float(material.optical_density))?;
+        writeln!(out, "d {}", options.float(material.dissolve))?;
+        writeln!(out, "illum {}", material.illumination)?;
+        let maps = [
+            ("map_Kd", &material.diffuse_map),
+            ("map_Ks", &material.specular_map),
+            ("map_Bump", &material.bump_map),
+            ("map_d", &material.dissolve_map),
+        ];
+        for (keyword, map) in maps {
+            if let Some(map) = map {
+                writeln!(out, "{} {}", keyword, map_path(map, directory).display())?;
+            }
+        }
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    // Faces before any object, objects with several groups, smoothing groups
+    // switched on and off, materials changing mid-group, polylines and points
+    const OBJ: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0 2
+v 0.5 0.5 1
+vt 0 0
+vt 1 0
+vt 1 1 0.5
+vn 0 0 1
+vn 0 1 0
+f 1 2 3
+o first
+g left right
+usemtl red
+s 1
+f 1/1/1 2/2/1 3/3/1 4/1/1
+g other
+s off
+usemtl blue
+f 1//2 3//2 5//2
+o second
+usemtl red
+s 2
+f 2/1 3/2 5/3
+l 1 2 3
+l 4 5
+p 1 5
+";
+
+    fn parse(text: &str) -> Scene {
+        Parser::from_reader(text.as_bytes(), "<test>")
+            .generate_scene()
+            .unwrap()
+    }
+
+    fn write(scene: &Scene) -> String {
+        let mut out = Vec::new();
+        write_obj(scene, &mut out, None, &WriteOptions::default()).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    // Everything the writer has to keep, one line per element
+    fn summary(scene: &Scene) -> Vec<String> {
+        let mut lines = Vec::new();
+        for v in scene.vertices.iter() {
+            lines.push(format!("v {} {} {} {}", v.x, v.y, v.z, v.w));
+        }
+        for vt in scene.texture_coordinates.iter() {
+            lines.push(format!("vt {} {} {}", vt.u, vt.v, vt.w));
+        }
+        for vn in scene.normals.iter() {
+            lines.push(format!("vn {} {} {}", vn.x, vn.y, vn.z));
+        }
+        for t in scene.triangles.iter() {
+            let corners = t.corners.map(|c| (c.vertex, c.texture, c.normal));
+            let material = t.material.map(|m| &scene.materials[m].name);
+            lines.push(format!(
+                "f {:?} s {} m {:?}",
+                corners, t.smoothing_group, material
+            ));
+        }
+        for object in scene.objects.iter() {
+            lines.push(format!("o {:?} {:?}", object.name, object.triangles));
+            for group in object.groups.iter() {
+                lines.push(format!("g {:?} {:?}", group.names, group.triangles));
+            }
+        }
+        lines.push(format!("l {:?}", scene.lines));
+        lines.push(format!("p {:?}", scene.points));
+        lines
+    }
+
+    #[test]
+    fn round_trip_keeps_the_scene() {
+        let scene = parse(OBJ);
+        assert_eq!(scene.objects.len(), 3);
+        assert_eq!(scene.materials.len(), 2);
+        let reparsed = parse(&write(&scene));
+        assert_eq!(summary(&reparsed), summary(&scene));
+    }
+
+    #[test]
+    fn writing_twice_gives_the_same_text() {
+        let first = write(&parse(OBJ));
+        assert_eq!(write(&parse(&first)), first);
+    }
+}